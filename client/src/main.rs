@@ -1,40 +1,69 @@
+use futures::{
+    stream::{SplitSink, SplitStream, StreamExt},
+    SinkExt,
+};
 use machineid_rs::{Encryption, HWIDComponent, IdBuilder};
 use shared::{
-    decoder::ReceiveFromStream,
+    capabilities::Capabilities,
+    codec::PacketCodec,
+    encryption::PublicKey,
+    handshake::{self, Hello, Negotiated, PROTOCOL_VERSIONS},
     messages::{
         client::{AuthenticationResponse, ClientPackets, KeepAliveResponse},
-        server::{AuthenticationRequest, KeepAliveRequest, ServerMessageType},
-        SystemPacket,
+        server::ServerPackets,
     },
     types::Hwid,
-    ADDR, PORT,
-};
-use std::{
-    io::{self, Cursor},
-    process,
+    ADDR, PORT, SERVER_PUBLIC_KEY_PATH,
 };
+use std::{env, fs, io, process};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        TcpStream,
-    },
+    net::TcpStream,
     spawn,
     sync::mpsc::{channel, Receiver, Sender},
 };
+use tokio_util::codec::Framed;
 
 const KEY: &str = "HASHING_KEY";
+/// Connects without a pinned server key, leaving the session unencrypted
+const INSECURE_FLAG: &str = "--insecure";
+const CAPABILITIES: Capabilities = Capabilities::CHAT.union(Capabilities::COMPRESSION).union(Capabilities::ENCRYPTION);
+
+type ClientFramed = Framed<TcpStream, PacketCodec<ServerPackets, ClientPackets>>;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    if let Ok(stream) = TcpStream::connect(format!("{ADDR}:{PORT}")).await {
+    let server_key = load_server_key()?;
+    if server_key.is_none() {
+        if !env::args().any(|arg| arg == INSECURE_FLAG) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No {SERVER_PUBLIC_KEY_PATH} to pin, pass {INSECURE_FLAG} to connect without encryption"),
+            ));
+        }
+
+        println!("> No {SERVER_PUBLIC_KEY_PATH} to pin, the connection won't be encrypted");
+    }
+
+    if let Ok(mut stream) = TcpStream::connect(format!("{ADDR}:{PORT}")).await {
+        let hello = Hello::new(PROTOCOL_VERSIONS, CAPABILITIES);
+        let mut negotiated = match handshake::connect(&mut stream, hello, server_key.as_ref()).await {
+            Ok(negotiated) => negotiated,
+            Err(why) => panic!("> Handshake with server failed: {why}"),
+        };
         println!("> Connected to server!");
 
-        let (reader, writer) = stream.into_split();
+        let (writer, reader) = Framed::new(
+            stream,
+            PacketCodec::default()
+                .with_capabilities(negotiated.capabilities)
+                .with_compression(negotiated.compression)
+                .with_session(negotiated.session.take()),
+        )
+        .split();
         let (sender, receiver) = channel::<ClientPackets>(100);
-        let chat_sender = sender.clone();
+        let _chat_sender = sender.clone();
 
-        spawn(async move { read_messages(reader, sender).await });
+        spawn(async move { read_messages(reader, negotiated, sender).await });
         spawn(async move { write_messages(writer, receiver).await });
 
         loop {
@@ -53,7 +82,23 @@ async fn main() -> io::Result<()> {
     }
 }
 
-pub async fn read_messages(mut reader: OwnedReadHalf, sender: Sender<ClientPackets>) {
+/// Reads the pinned public key of the server, if there is one
+fn load_server_key() -> io::Result<Option<PublicKey>> {
+    match fs::read(SERVER_PUBLIC_KEY_PATH) {
+        Ok(bytes) => <[u8; 32]>::try_from(bytes)
+            .map(|bytes| Some(PublicKey::from(bytes)))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{SERVER_PUBLIC_KEY_PATH} has to hold 32 bytes"))),
+        Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(why) => Err(why),
+    }
+}
+
+pub async fn read_messages(mut reader: SplitStream<ClientFramed>, negotiated: Negotiated, sender: Sender<ClientPackets>) {
+    println!(
+        "> Speaking protocol version {} with capabilities {}",
+        negotiated.version, negotiated.capabilities
+    );
+
     let cpu_id = IdBuilder::new(Encryption::SHA256)
         .add_component(HWIDComponent::CPUID)
         .build(KEY)
@@ -63,63 +108,42 @@ pub async fn read_messages(mut reader: OwnedReadHalf, sender: Sender<ClientPacke
         .build(KEY)
         .unwrap();
 
-    loop {
-        let len = reader.read_i32().await.unwrap_or(0) as usize;
-        if len == 0 {
-            println!("Server disconnected");
-            process::exit(0);
-        }
+    while let Some(packet) = reader.next().await {
+        match packet {
+            Ok(ServerPackets::AuthenticationRequest(req)) => {
+                println!("Received AuthenticationRequest {req:?}");
 
-        let mut buffer = vec![0u8; len];
-        match reader.read_exact(&mut buffer).await {
-            Ok(read) => {
-                if read == 0 {
-                    println!("Server disconnected");
-                    process::exit(0);
-                }
-
-                let mut cursor = Cursor::new(buffer);
-                match ServerMessageType::from(&mut cursor).await {
-                    ServerMessageType::AuthenticationRequest => {
-                        let req = AuthenticationRequest::from_bytes(&mut cursor).await.unwrap();
-                        println!("Received AuthenticationRequest {req:?}");
-
-                        sender
-                            .send(ClientPackets::AuthenticationResponse(AuthenticationResponse {
-                                hwid: Hwid {
-                                    cpu_id: cpu_id.clone(),
-                                    system_id: system_id.clone(),
-                                },
-                                nonce: req.nonce,
-                            }))
-                            .await
-                            .unwrap();
-                    }
-                    ServerMessageType::KeepAliveRequest => {
-                        let req = KeepAliveRequest::from_bytes(&mut cursor).await.unwrap();
-                        println!("Received KeepAliveRequest {req:?}");
-                        sender
-                            .send(ClientPackets::KeepAliveResponse(KeepAliveResponse { timestamp: req.timestamp }))
-                            .await
-                            .unwrap();
-                    }
-                    _ => panic!("Received invalid packet!"),
-                }
+                sender
+                    .send(
+                        AuthenticationResponse {
+                            hwid: Hwid {
+                                cpu_id: cpu_id.clone(),
+                                system_id: system_id.clone(),
+                            },
+                            nonce: req.nonce,
+                        }
+                        .into(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            Ok(ServerPackets::KeepAliveRequest(req)) => {
+                println!("Received KeepAliveRequest {req:?}");
+                sender.send(KeepAliveResponse { timestamp: req.timestamp }.into()).await.unwrap();
+            }
+            Err(why) => {
+                println!("Server sent an invalid packet, disconnecting: {why}");
+                process::exit(1);
             }
-            Err(why) => panic!("{why}"),
         }
     }
+
+    println!("Server disconnected");
+    process::exit(0);
 }
 
-pub async fn write_messages(mut writer: OwnedWriteHalf, mut receiver: Receiver<ClientPackets>) {
+pub async fn write_messages(mut writer: SplitSink<ClientFramed, ClientPackets>, mut receiver: Receiver<ClientPackets>) {
     while let Some(recv) = receiver.recv().await {
-        let buffer = match recv {
-            ClientPackets::AuthenticationResponse(x) => x.to_bytes().await,
-            ClientPackets::KeepAliveResponse(x) => x.to_bytes().await,
-            _ => panic!("Invalid packet received!"),
-        }
-        .unwrap();
-
-        writer.write_all(&buffer[..]).await.unwrap();
+        writer.send(recv).await.unwrap();
     }
 }
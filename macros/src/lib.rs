@@ -1,123 +1,1196 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields};
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{
+    parenthesized, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, token, Attribute, Data, DataEnum, DeriveInput,
+    Error, Expr, ExprLit, ExprUnary, Field, Fields, Generics, Ident, Lit, LitInt, LitStr, Path, Result, Token, Type, UnOp, WherePredicate,
+};
 
-/// # Information
-/// Makes the struct serializable for `T`
-#[proc_macro_derive(Serialize, attributes(serialize_as))]
-pub fn derive_serialize(input: TokenStream) -> TokenStream {
-    let ast = parse_macro_input!(input as DeriveInput);
-    let struct_name = &ast.ident;
+/// The length prefix requested with `#[len(u8 | u16 | u32 | varint)]`
+fn length_prefix(attribute: &Attribute, krate: &Path) -> Result<TokenStream2> {
+    let width: Ident = attribute.parse_args()?;
 
-    match ast.data {
-        Data::Struct(data_struct) => match data_struct.fields {
-            Fields::Named(fields) => {
-                let field_names = fields.named.iter().map(|field| &field.ident);
+    let variant = match width.to_string().as_str() {
+        "u8" => quote!(U8),
+        "u16" => quote!(U16),
+        "u32" => quote!(U32),
+        "varint" => quote!(VarInt),
+        _ => {
+            return Err(Error::new(
+                width.span(),
+                "expected one of `u8`, `u16`, `u32` or `varint` as the length prefix width",
+            ))
+        }
+    };
 
-                quote! {
-                    impl Encoder for #struct_name {
-                        async fn encode<W: tokio::io::AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), crate::errors::encode::EncodeError> {
-                            #(self.#field_names.encode(writer).await?;)*
-                            Ok(())
+    Ok(quote!(#krate::prefix::LengthPrefix::#variant))
+}
+
+/// The byte order requested with `#[endian(big | little)]`
+fn endian(attribute: &Attribute, krate: &Path) -> Result<TokenStream2> {
+    let order: Ident = attribute.parse_args()?;
+
+    let variant = match order.to_string().as_str() {
+        "big" => quote!(Big),
+        "little" => quote!(Little),
+        _ => return Err(Error::new(order.span(), "expected `big` or `little` as the byte order")),
+    };
+
+    Ok(quote!(#krate::context::Endian::#variant))
+}
+
+/// The settings of the attributes on a field
+#[derive(Default)]
+struct FieldAttrs {
+    /// `#[varint]`, the field is written as a `VarInt`
+    varint: bool,
+    /// `#[len(..)]`, the length prefix of the field
+    prefix: Option<TokenStream2>,
+    /// `#[endian(..)]`, the byte order of the field, wins over the one of the type
+    endian: Option<TokenStream2>,
+    /// `#[serialize_as(T)]`, the type the field is converted into on the wire
+    serialize_as: Option<Type>,
+    /// `#[codec(skip)]`, the field is never on the wire
+    skip: bool,
+    /// `#[codec(default = expr)]`, the value of a skipped or absent field instead of `Default::default()`
+    default: Option<Expr>,
+    /// `#[codec(with = "module")]`, a module with `encode` and `decode` functions used instead of the codec traits
+    with: Option<Path>,
+    /// `#[codec(if = "expr")]`, the field is only on the wire while the condition over earlier fields holds
+    condition: Option<Expr>,
+}
+
+impl FieldAttrs {
+    fn from_field(field: &Field, krate: &Path) -> Result<Self> {
+        let mut attrs = Self::default();
+
+        for attribute in &field.attrs {
+            let path = attribute.path();
+
+            if path.is_ident("varint") {
+                attribute.meta.require_path_only()?;
+                attrs.varint = true;
+            } else if path.is_ident("len") {
+                attrs.prefix = Some(length_prefix(attribute, krate)?);
+            } else if path.is_ident("endian") {
+                attrs.endian = Some(endian(attribute, krate)?);
+            } else if path.is_ident("serialize_as") {
+                attrs.serialize_as = Some(attribute.parse_args()?);
+            } else if path.is_ident("codec") {
+                attribute.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("skip") {
+                        attrs.skip = true;
+                    } else if meta.path.is_ident("default") {
+                        attrs.default = Some(meta.value()?.parse()?);
+                    } else if meta.path.is_ident("with") {
+                        attrs.with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    } else if meta.path.is_ident("if") {
+                        attrs.condition = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    } else {
+                        return Err(meta.error("unknown codec attribute, expected `skip`, `default`, `with` or `if`"));
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+
+        if attrs.varint && attrs.prefix.is_some() {
+            return Err(Error::new_spanned(field, "`#[varint]` and `#[len(..)]` can't be combined"));
+        }
+
+        Ok(attrs)
+    }
+
+    /// The value of the field when it isn't read from the wire
+    fn default_value(&self) -> TokenStream2 {
+        match &self.default {
+            Some(default) => quote!(#default),
+            None => quote!(::core::default::Default::default()),
+        }
+    }
+}
+
+/// Collects every identifier used in `tokens`, including inside groups
+fn idents(tokens: TokenStream2, found: &mut Vec<Ident>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => found.push(ident),
+            TokenTree::Group(group) => idents(group.stream(), found),
+            _ => {}
+        }
+    }
+}
+
+/// Whether the field is a primitive, whose value a `#[codec(if = "..")]` condition sees instead of a reference
+fn is_primitive(ty: &Type) -> bool {
+    const PRIMITIVES: &[&str] = &[
+        "bool", "char", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+    ];
+
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().is_some_and(|ident| PRIMITIVES.iter().any(|p| ident == p)),
+        _ => false,
+    }
+}
+
+/// How a condition sees the earlier field `field`, given an expression borrowing its value
+fn condition_binding(field: &Field, value: &TokenStream2) -> TokenStream2 {
+    match is_primitive(&field.ty) {
+        true => quote!(*#value),
+        false => value.clone(),
+    }
+}
+
+/// Binds the earlier fields a `#[codec(if = "..")]` condition refers to, then evaluates it.
+/// `earlier` pairs the name of every earlier field on the wire with the expression it is bound to, see `condition_binding`.
+/// `hidden` names the earlier fields the decoder can't see the real value of, which the condition must not use.
+fn condition(condition: &Expr, earlier: &[(Ident, TokenStream2)], hidden: &[(Ident, &str)]) -> Result<TokenStream2> {
+    let mut used = vec![];
+    idents(condition.to_token_stream(), &mut used);
+
+    if let Some((name, why)) = hidden.iter().find(|(name, _)| used.contains(name)) {
+        return Err(Error::new_spanned(
+            condition,
+            format!("the condition can't use `{name}`, which {why}, so the decoder wouldn't see the value the encoder saw"),
+        ));
+    }
+
+    let bindings = earlier
+        .iter()
+        .filter(|(name, _)| used.contains(name))
+        .map(|(name, value)| quote! { let #name = #value; });
+
+    Ok(quote! {
+        {
+            #(#bindings)*
+            #condition
+        }
+    })
+}
+
+/// Why the decoder doesn't know the real value of a field, if it doesn't
+fn hidden_reason(attrs: &FieldAttrs) -> Option<&'static str> {
+    match (attrs.skip, &attrs.condition) {
+        (true, _) => Some("is skipped"),
+        (false, Some(_)) => Some("is only on the wire under its own condition"),
+        (false, None) => None,
+    }
+}
+
+/// Generates the statements that write the fields into `writer`.
+/// `values` holds an expression borrowing the value of each field.
+fn encode_fields<'a>(fields: impl IntoIterator<Item = &'a Field>, values: &[TokenStream2], container: &Container) -> Result<TokenStream2> {
+    let mut earlier = vec![];
+    let mut hidden = vec![];
+    let mut statements = vec![];
+
+    for (field, value) in fields.into_iter().zip(values) {
+        let attrs = FieldAttrs::from_field(field, &container.krate)?;
+
+        if !attrs.skip {
+            let statement = encode_field(field, &attrs, container, value);
+
+            statements.push(match &attrs.condition {
+                Some(expr) => {
+                    let condition = condition(expr, &earlier, &hidden)?;
+                    quote! {
+                        if #condition {
+                            #statement
                         }
                     }
                 }
+                None => statement,
+            });
+        }
+
+        if let Some(name) = &field.ident {
+            match hidden_reason(&attrs) {
+                Some(why) => hidden.push((name.clone(), why)),
+                None => earlier.push((name.clone(), condition_binding(field, value))),
+            }
+        }
+    }
+
+    Ok(quote! { #(#statements)* })
+}
+
+/// Generates the statements that read the fields from `reader` into the returned locals
+fn decode_fields<'a>(fields: impl IntoIterator<Item = &'a Field>, container: &Container) -> Result<(TokenStream2, Vec<Ident>)> {
+    let mut earlier = vec![];
+    let mut hidden = vec![];
+    let mut statements = vec![];
+    let mut locals = vec![];
+
+    for (i, field) in fields.into_iter().enumerate() {
+        let attrs = FieldAttrs::from_field(field, &container.krate)?;
+        let local = match &field.ident {
+            Some(name) => format_ident!("field_{}", name),
+            None => format_ident!("field_{}", i),
+        };
+
+        let value = match (attrs.skip, &attrs.condition) {
+            (true, _) => attrs.default_value(),
+            (false, Some(expr)) => {
+                let condition = condition(expr, &earlier, &hidden)?;
+                let value = decode_field(field, &attrs, container);
+                let default = attrs.default_value();
+                quote! {
+                    if #condition {
+                        #value
+                    } else {
+                        #default
+                    }
+                }
+            }
+            (false, None) => decode_field(field, &attrs, container),
+        };
+        statements.push(quote! { let #local = #value; });
+
+        if let Some(name) = &field.ident {
+            match hidden_reason(&attrs) {
+                Some(why) => hidden.push((name.clone(), why)),
+                None => earlier.push((name.clone(), condition_binding(field, &quote!(&#local)))),
+            }
+        }
+        locals.push(local);
+    }
+
+    Ok((quote! { #(#statements)* }, locals))
+}
+
+/// Generates the statement that writes the field borrowed by `value` into `writer`.
+/// A field level `#[endian(..)]` wins over the struct level one, without either the caller's context is used as is.
+fn encode_field(field: &Field, attrs: &FieldAttrs, container: &Container, value: &TokenStream2) -> TokenStream2 {
+    let krate = &container.krate;
+    let field_type = &field.ty;
+    let ctx = match attrs.endian.as_ref().or(container.endian.as_ref()) {
+        Some(endian) => quote!(&ctx.with_endian(#endian)),
+        None => quote!(ctx),
+    };
+
+    // With `#[serialize_as(T)]` a converted copy of the field is what gets encoded
+    match &attrs.serialize_as {
+        Some(wire_type) => {
+            let encode = encode_value(attrs, container, wire_type, &quote!(&value), &ctx);
+            quote! {
+                {
+                    let value = <#wire_type as ::core::convert::TryFrom<#field_type>>::try_from(::core::clone::Clone::clone(#value)).map_err(|_| {
+                        #krate::errors::encode::EncodeError::Conversion {
+                            from: ::core::any::type_name::<#field_type>(),
+                            to: ::core::any::type_name::<#wire_type>(),
+                        }
+                    })?;
+                    #encode
+                }
+            }
+        }
+        None => encode_value(attrs, container, field_type, value, &ctx),
+    }
+}
+
+/// Generates the statement that writes `value`, a borrowed `field_type`, into `writer` as the field's attributes request
+fn encode_value(attrs: &FieldAttrs, container: &Container, field_type: &Type, value: &TokenStream2, ctx: &TokenStream2) -> TokenStream2 {
+    let krate = &container.krate;
+
+    if let Some(with) = &attrs.with {
+        quote! { #with::encode(#value, writer, #ctx).await?; }
+    } else if attrs.varint {
+        quote! { #krate::encoder::Encoder::encode_with(&#krate::varint::VarInt::<#field_type>(*#value), writer, #ctx).await?; }
+    } else if let Some(prefix) = &attrs.prefix {
+        quote! { #krate::encoder::EncodePrefixed::encode_prefixed(#value, writer, #prefix, #ctx).await?; }
+    } else {
+        quote! { #krate::encoder::Encoder::encode_with(#value, writer, #ctx).await?; }
+    }
+}
+
+/// Generates the expression that reads a value of the field's type from `reader`
+fn decode_field(field: &Field, attrs: &FieldAttrs, container: &Container) -> TokenStream2 {
+    let krate = &container.krate;
+    let field_type = attrs.serialize_as.as_ref().unwrap_or(&field.ty);
+
+    let value = if let Some(with) = &attrs.with {
+        quote! { #with::decode(reader, ctx).await }
+    } else if attrs.varint {
+        quote! { <#krate::varint::VarInt<#field_type> as #krate::decoder::Decoder>::decode_with(reader, ctx).await.map(|v| v.0) }
+    } else if let Some(prefix) = &attrs.prefix {
+        quote! { <#field_type as #krate::decoder::DecodePrefixed>::decode_prefixed(reader, #prefix, ctx).await }
+    } else {
+        quote! { <#field_type as #krate::decoder::Decoder>::decode_with(reader, ctx).await }
+    };
+
+    // With `#[serialize_as(T)]` the value read is a `T` that still has to be converted into the field's type
+    let value = match &attrs.serialize_as {
+        Some(wire_type) => {
+            let field_type = &field.ty;
+            quote! {
+                #value.and_then(|value| {
+                    <#field_type as ::core::convert::TryFrom<#wire_type>>::try_from(value).map_err(|_| {
+                        #krate::errors::decode::DecodeError::Conversion {
+                            from: ::core::any::type_name::<#wire_type>(),
+                            to: ::core::any::type_name::<#field_type>(),
+                        }
+                    })
+                })
+            }
+        }
+        None => value,
+    };
+
+    with_decode_endian(attrs.endian.as_ref().or(container.endian.as_ref()), value)
+}
+
+/// Evaluates the decode expression `value` with `ctx.endian` temporarily overridden, then applies `?`
+fn with_decode_endian(endian: Option<&TokenStream2>, value: TokenStream2) -> TokenStream2 {
+    match endian {
+        Some(endian) => quote! {
+            {
+                let outer_endian = ctx.endian;
+                ctx.endian = #endian;
+                let value = #value;
+                ctx.endian = outer_endian;
+                value?
             }
-            .into(),
-            _ => panic!("Expected a named field"),
         },
-        _ => panic!("Expected a struct or an enum with named fields"),
+        None => quote! { #value? },
     }
 }
 
-#[proc_macro_derive(Deserialize)]
-pub fn derive_deserialize(input: TokenStream) -> TokenStream {
-    let ast = parse_macro_input!(input as DeriveInput);
-    let struct_name = &ast.ident;
+/// Which of the generated impls the bounds are for
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Serialize,
+    Deserialize,
+}
+
+/// The type level settings shared by all derives
+struct Container {
+    /// The path of the `shared` crate, `::shared` unless overridden with `#[codec(crate = "..")]`
+    krate: Path,
+    /// The byte order of `#[endian(..)]` on the type, if any
+    endian: Option<TokenStream2>,
+    /// Whether the type is marked `#[transparent]`
+    transparent: bool,
+    /// The where-predicates of `#[codec(bound = "..")]` or `#[codec(bound(serialize = "..", deserialize = ".."))]`
+    serialize_bounds: Option<Vec<WherePredicate>>,
+    deserialize_bounds: Option<Vec<WherePredicate>>,
+}
 
-    match ast.data {
-        Data::Struct(data_struct) => match data_struct.fields {
-            Fields::Named(fields) => {
-                let fields = fields.named.iter().map(|field| {
-                    let field_name = &field.ident.clone().unwrap();
-                    let field_type = &field.ty;
+impl Container {
+    fn from_ast(ast: &DeriveInput) -> Result<Self> {
+        if let Data::Union(data_union) = &ast.data {
+            return Err(Error::new_spanned(
+                data_union.union_token,
+                "unions can't be derived, expected a struct or an enum",
+            ));
+        }
 
-                    quote! {
-                        #field_name: <#field_type>::decode(reader).await?,
+        let mut krate = parse_quote!(::shared);
+        let mut serialize_bounds = None;
+        let mut deserialize_bounds = None;
+        let parse = |lit: LitStr| {
+            lit.parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)
+                .map(|predicates| predicates.into_iter().collect::<Vec<_>>())
+        };
+
+        for attribute in ast.attrs.iter().filter(|a| a.path().is_ident("codec")) {
+            attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("crate") {
+                    krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                    return Ok(());
+                }
+
+                if !meta.path.is_ident("bound") {
+                    return Err(meta.error("unknown codec attribute, expected `crate` or `bound`"));
+                }
+
+                if meta.input.peek(Token![=]) {
+                    let bounds = parse(meta.value()?.parse()?)?;
+                    serialize_bounds = Some(bounds.clone());
+                    deserialize_bounds = Some(bounds);
+                    return Ok(());
+                }
+
+                meta.parse_nested_meta(|meta| {
+                    let bounds = parse(meta.value()?.parse()?)?;
+                    if meta.path.is_ident("serialize") {
+                        serialize_bounds = Some(bounds);
+                    } else if meta.path.is_ident("deserialize") {
+                        deserialize_bounds = Some(bounds);
+                    } else {
+                        return Err(meta.error("expected `serialize` or `deserialize`"));
+                    }
+                    Ok(())
+                })
+            })?;
+        }
+
+        let endian = ast
+            .attrs
+            .iter()
+            .find(|a| a.path().is_ident("endian"))
+            .map(|a| endian(a, &krate))
+            .transpose()?;
+
+        let transparent = match ast.attrs.iter().find(|a| a.path().is_ident("transparent")) {
+            Some(attribute) => match &ast.data {
+                Data::Struct(data_struct) if data_struct.fields.len() == 1 => {
+                    attribute.meta.require_path_only()?;
+                    true
+                }
+                _ => {
+                    return Err(Error::new_spanned(
+                        attribute,
+                        "`#[transparent]` requires a struct with exactly one field",
+                    ))
+                }
+            },
+            None => false,
+        };
+
+        Ok(Self {
+            krate,
+            endian,
+            transparent,
+            serialize_bounds,
+            deserialize_bounds,
+        })
+    }
+
+    /// The generics of the type with every type parameter bound by the codec trait,
+    /// or by the predicates of `#[codec(bound = "..")]` when given
+    fn bounded_generics(&self, generics: &Generics, direction: Direction) -> Generics {
+        let krate = &self.krate;
+        let custom_bounds = match direction {
+            Direction::Serialize => &self.serialize_bounds,
+            Direction::Deserialize => &self.deserialize_bounds,
+        };
+        let predicates = custom_bounds.clone().unwrap_or_else(|| {
+            generics
+                .type_params()
+                .map(|param| {
+                    let ident = &param.ident;
+                    match direction {
+                        Direction::Serialize => parse_quote!(#ident: #krate::encoder::Encoder + ::core::marker::Sync),
+                        Direction::Deserialize => parse_quote!(#ident: #krate::decoder::Decoder<Output = #ident>),
+                    }
+                })
+                .collect()
+        });
+
+        let mut generics = generics.clone();
+        generics.make_where_clause().predicates.extend(predicates);
+        generics
+    }
+}
+
+/// The integer type named in `#[repr(..)]`, which is also the type of the enum tag on the wire (`u8` by default)
+fn tag_type(attrs: &[Attribute]) -> Result<Ident> {
+    let mut tag_type = format_ident!("u8");
+
+    for attribute in attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attribute.parse_nested_meta(|meta| {
+            const INTEGERS: [&str; 8] = ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"];
+            if let Some(ident) = meta.path.get_ident().filter(|i| INTEGERS.contains(&i.to_string().as_str())) {
+                tag_type = ident.clone();
+            } else if meta.input.peek(token::Paren) {
+                // Skips the arguments of representations like `align(4)`
+                let _arguments;
+                parenthesized!(_arguments in meta.input);
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(tag_type)
+}
+
+/// The range of values that fit into the tag type
+fn tag_range(tag_type: &Ident) -> (i128, i128) {
+    match tag_type.to_string().as_str() {
+        "u8" => (0, u8::MAX.into()),
+        "u16" => (0, u16::MAX.into()),
+        "u32" => (0, u32::MAX.into()),
+        "u64" => (0, u64::MAX.into()),
+        "i8" => (i8::MIN.into(), i8::MAX.into()),
+        "i16" => (i16::MIN.into(), i16::MAX.into()),
+        "i32" => (i32::MIN.into(), i32::MAX.into()),
+        _ => (i64::MIN.into(), i64::MAX.into()),
+    }
+}
+
+/// Parses an integer literal, optionally negated, as used in discriminants
+fn integer_literal(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse().ok(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_), expr, ..
+        }) => integer_literal(expr).map(|n: i128| -n),
+        _ => None,
+    }
+}
+
+/// The wire tag of every variant. `#[tag(n)]` wins over an explicit discriminant,
+/// otherwise the tag follows the previous variant just like implicit discriminants do.
+fn variant_tags(data: &DataEnum, tag_type: &Ident) -> Result<Vec<i128>> {
+    let (min, max) = tag_range(tag_type);
+    let mut tags = Vec::with_capacity(data.variants.len());
+    let mut next = 0;
+
+    for variant in &data.variants {
+        let (tag, span) = match variant.attrs.iter().find(|a| a.path().is_ident("tag")) {
+            Some(attribute) => {
+                let expr: Expr = attribute.parse_args()?;
+                let tag = integer_literal(&expr).ok_or_else(|| Error::new_spanned(&expr, "expected an integer tag, like `#[tag(1)]`"))?;
+                (tag, expr.span())
+            }
+            None => match &variant.discriminant {
+                Some((_, expr)) => {
+                    let tag = integer_literal(expr)
+                        .ok_or_else(|| Error::new_spanned(expr, "expected an integer literal as the discriminant, or a `#[tag(n)]`"))?;
+                    (tag, expr.span())
+                }
+                None => (next, variant.ident.span()),
+            },
+        };
+
+        if tag < min || tag > max {
+            return Err(Error::new(
+                span,
+                format!("tag {tag} of variant `{}` doesn't fit into `{tag_type}`", variant.ident),
+            ));
+        }
+        if tags.contains(&tag) {
+            return Err(Error::new(
+                span,
+                format!("tag {tag} of variant `{}` is already used by another variant", variant.ident),
+            ));
+        }
+
+        tags.push(tag);
+        next = tag + 1;
+    }
+
+    Ok(tags)
+}
+
+/// Generates the statements that write `self` into `writer`
+fn encode_body(ast: &DeriveInput, container: &Container) -> Result<TokenStream2> {
+    let krate = &container.krate;
+
+    match &ast.data {
+        Data::Struct(data_struct) => {
+            let values = data_struct
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| match &field.ident {
+                    Some(name) => quote!(&self.#name),
+                    None => {
+                        let index = syn::Index::from(i);
+                        quote!(&self.#index)
                     }
-                });
+                })
+                .collect::<Vec<_>>();
 
-                let gen = quote! {
-                    impl crate::decoder::Decoder for #struct_name {
-                        type Output = Self;
+            encode_fields(&data_struct.fields, &values, container)
+        }
+        Data::Enum(data_enum) => {
+            let tag_type = tag_type(&ast.attrs)?;
+            let tags = variant_tags(data_enum, &tag_type)?;
 
-                        async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-                            Ok(Self { #(#fields)* })
+            let arms = data_enum
+                .variants
+                .iter()
+                .zip(tags)
+                .map(|(variant, tag)| {
+                    let variant_name = &variant.ident;
+                    let tag = proc_macro2::Literal::i128_unsuffixed(tag);
+                    let tag_ctx = match &container.endian {
+                        Some(endian) => quote!(&ctx.with_endian(#endian)),
+                        None => quote!(ctx),
+                    };
+
+                    // The fields are bound by reference to `field_<name>` so they can't shadow `writer` or `ctx`
+                    let bindings = variant
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .map(|(i, field)| match &field.ident {
+                            Some(name) => format_ident!("field_{}", name),
+                            None => format_ident!("field_{}", i),
+                        })
+                        .collect::<Vec<_>>();
+                    let values = bindings.iter().map(|binding| quote!(#binding)).collect::<Vec<_>>();
+                    let encode_fields = encode_fields(&variant.fields, &values, container)?;
+
+                    let pattern = match &variant.fields {
+                        Fields::Named(fields) => {
+                            let names = fields.named.iter().map(|f| &f.ident);
+                            quote!(Self::#variant_name { #(#names: #bindings),* })
                         }
+                        Fields::Unnamed(_) => quote!(Self::#variant_name(#(#bindings),*)),
+                        Fields::Unit => quote!(Self::#variant_name),
+                    };
+
+                    Ok(quote! {
+                        #[allow(unused_variables)]
+                        #pattern => {
+                            let tag: #tag_type = #tag;
+                            #krate::encoder::Encoder::encode_with(&tag, writer, #tag_ctx).await?;
+                            #encode_fields
+                        }
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(quote! {
+                match self {
+                    #(#arms)*
+                }
+            })
+        }
+        Data::Union(_) => unreachable!("unions are rejected by `Container::from_ast`"),
+    }
+}
+
+/// Generates the expression that builds `path` (`Self` or a variant of it) from its fields, read from `reader`
+fn decode_construct(path: TokenStream2, fields: &Fields, container: &Container) -> Result<TokenStream2> {
+    let (statements, locals) = decode_fields(fields, container)?;
+
+    let construct = match fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|f| &f.ident);
+            quote!(#path { #(#names: #locals),* })
+        }
+        Fields::Unnamed(_) => quote!(#path(#(#locals),*)),
+        Fields::Unit => quote!(#path),
+    };
+
+    Ok(quote! {
+        {
+            #statements
+            #construct
+        }
+    })
+}
+
+/// Generates the expression that reads a `Self` from `reader`
+fn decode_body(ast: &DeriveInput, container: &Container) -> Result<TokenStream2> {
+    let krate = &container.krate;
+    let name = &ast.ident;
+
+    match &ast.data {
+        Data::Struct(data_struct) => decode_construct(quote!(Self), &data_struct.fields, container),
+        Data::Enum(data_enum) => {
+            let tag_type = tag_type(&ast.attrs)?;
+            let tags = variant_tags(data_enum, &tag_type)?;
+
+            let arms = data_enum
+                .variants
+                .iter()
+                .zip(tags)
+                .map(|(variant, tag)| {
+                    let variant_name = &variant.ident;
+                    let tag = proc_macro2::Literal::i128_unsuffixed(tag);
+                    let construct = decode_construct(quote!(Self::#variant_name), &variant.fields, container)?;
+
+                    Ok(quote! { #tag => #construct, })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let decode_tag = with_decode_endian(
+                container.endian.as_ref(),
+                quote!(<#tag_type as #krate::decoder::Decoder>::decode_with(reader, ctx).await),
+            );
+
+            Ok(quote! {
+                match #decode_tag {
+                    #(#arms)*
+                    tag => {
+                        return ::core::result::Result::Err(#krate::errors::decode::DecodeError::UnknownVariant {
+                            name: ::core::stringify!(#name),
+                            tag: tag as i128,
+                        })
                     }
-                };
+                }
+            })
+        }
+        Data::Union(_) => unreachable!("unions are rejected by `Container::from_ast`"),
+    }
+}
+
+/// The `PacketNamespace` variant named by `core`, `application` or `extensions`
+fn namespace(ident: &Ident) -> Result<Ident> {
+    let variant = match ident.to_string().as_str() {
+        "core" => "Core",
+        "application" => "Application",
+        "extensions" => "Extensions",
+        _ => {
+            return Err(Error::new(
+                ident.span(),
+                "unknown packet namespace, expected `core`, `application` or `extensions`",
+            ))
+        }
+    };
+
+    Ok(Ident::new(variant, ident.span()))
+}
+
+/// The largest offset into a namespace returned by `namespace`, mirrors `PacketNamespace::range`
+fn last_offset(namespace: &Ident) -> u16 {
+    match namespace.to_string().as_str() {
+        "Core" => 0x00FF,
+        "Application" => 0x7FFF - 0x0100,
+        _ => 0xFFFF - 0x8000,
+    }
+}
+
+/// The id given with `#[packet_id(n)]`, which has to appear exactly once.
+/// `#[packet_id(namespace = n)]` counts the id from the start of the namespace instead.
+/// `#[packet_id(auto)]` leaves the id to the `PacketSet` the packet belongs to and yields `None`.
+fn packet_id(ast: &DeriveInput, krate: &Path) -> Result<Option<TokenStream2>> {
+    let mut attributes = ast.attrs.iter().filter(|a| a.path().is_ident("packet_id"));
+
+    let attribute = attributes
+        .next()
+        .ok_or_else(|| Error::new(ast.ident.span(), "missing packet id, add `#[packet_id(n)]` to the type"))?;
+    if let Some(duplicate) = attributes.next() {
+        return Err(Error::new_spanned(
+            duplicate,
+            "duplicate packet id, `#[packet_id(..)]` may only appear once",
+        ));
+    }
+
+    if attribute.parse_args::<Ident>().is_ok_and(|ident| ident == "auto") {
+        return Ok(None);
+    }
+
+    let (namespace, lit) = attribute
+        .parse_args_with(|input: syn::parse::ParseStream| {
+            let namespace = match input.peek(Ident) {
+                true => {
+                    let namespace: Ident = input.parse()?;
+                    input.parse::<Token![=]>()?;
+                    Some(namespace)
+                }
+                false => None,
+            };
+            Ok((namespace, input.parse::<LitInt>()?))
+        })
+        .map_err(|e| {
+            Error::new(
+                e.span(),
+                "expected an integer packet id like `#[packet_id(0x0101)]` or `#[packet_id(application = 0x01)]`, \
+                 or `#[packet_id(auto)]`",
+            )
+        })?;
+    let id: u128 = lit.base10_parse()?;
+    let id = u16::try_from(id).map_err(|_| Error::new(lit.span(), format!("packet id {id} is out of range, expected 0..=65535")))?;
 
-                gen.into()
+    Ok(Some(match namespace {
+        Some(name) => {
+            let namespace = self::namespace(&name)?;
+            let last = last_offset(&namespace);
+            if id > last {
+                return Err(Error::new(
+                    lit.span(),
+                    format!("packet id offset {id:#x} is past the end of the `{name}` namespace, expected 0..={last:#x}"),
+                ));
             }
-            _ => panic!("Expected a named field"),
-        },
-        _ => panic!("Expected a struct with named fields"),
+
+            quote!(#krate::messages::PacketNamespace::#namespace.id(#id))
+        }
+        None => quote!(#id),
+    }))
+}
+
+/// The capabilities named by `#[requires(..)]`, combined into one `Capabilities` expression
+fn required_capabilities(ast: &DeriveInput, krate: &Path) -> Result<Option<TokenStream2>> {
+    let mut capabilities = vec![];
+    for attribute in ast.attrs.iter().filter(|a| a.path().is_ident("requires")) {
+        let names = attribute
+            .parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)
+            .map_err(|e| Error::new(e.span(), "expected capability names like `#[requires(CHAT)]`"))?;
+        capabilities.extend(names);
+    }
+
+    if capabilities.is_empty() {
+        return Ok(None);
     }
+
+    // Spanned on the names, so a capability that doesn't exist is reported where it's written
+    let capabilities = capabilities
+        .iter()
+        .map(|name| quote_spanned!(name.span()=> .union(#krate::capabilities::Capabilities::#name)));
+    Ok(Some(quote!(#krate::capabilities::Capabilities::NONE #(#capabilities)*)))
+}
+
+/// # Information
+/// Makes the struct or enum serializable for `T`.
+/// Enums are written as a tag followed by the fields of the variant, see `Deserialize` for how tags are chosen.
+/// A `#[transparent]` newtype is written exactly like its only field.
+/// Type parameters are bound by `Encoder + Sync` unless `#[codec(bound = "..")]` replaces the bounds.
+/// The generated code refers to `::shared`, `#[codec(crate = "..")]` points it at a re-export instead.
+/// Fields can be left off the wire with `#[codec(skip)]` or `#[codec(if = "expr")]`, in which case decoding
+/// fills them from `#[codec(default = expr)]` or `Default`. `#[codec(with = "module")]` uses `module::encode` and `module::decode`.
+/// `#[serialize_as(T)]` converts the field into a `T` with `TryFrom` before writing it and back after reading it.
+///
+/// A condition sees the earlier fields by name. Fields of a primitive type (`bool`, `char`, integers and floats) are bound
+/// by value, every other field by reference. Skipped and conditional fields can't be used, the decoder doesn't know their values:
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Message {
+///     has_reply: bool,
+///     kind: Kind,
+///     #[codec(if = "has_reply && *kind == Kind::Question")]
+///     reply_to: u32,
+/// }
+/// ```
+#[proc_macro_derive(Serialize, attributes(serialize_as, varint, len, endian, tag, transparent, codec))]
+pub fn derive_serialize(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    expand_serialize(&ast).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_serialize(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let container = Container::from_ast(ast)?;
+    let krate = &container.krate;
+    let body = encode_body(ast, &container)?;
+    let generics = container.bounded_generics(&ast.generics, Direction::Serialize);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::encoder::Encoder for #name #ty_generics #where_clause {
+            async fn encode_with<W: #krate::__private::AsyncWrite + ::core::marker::Unpin + ::core::marker::Send>(
+                &self,
+                writer: &mut W,
+                ctx: &#krate::context::EncodeContext,
+            ) -> ::core::result::Result<(), #krate::errors::encode::EncodeError> {
+                #body
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
 }
 
-#[proc_macro_derive(Networked, attributes(packet_id))]
+/// # Information
+/// Makes the struct or enum deserializable.
+/// The tag of an enum variant is taken from `#[tag(n)]`, then from its discriminant and otherwise counts up from the previous variant.
+/// Its type on the wire is the integer type of `#[repr(..)]`, or `u8` without one.
+/// A `#[transparent]` newtype is read exactly like its only field and doesn't count towards the depth limit.
+/// Type parameters are bound by `Decoder<Output = T>` unless `#[codec(bound = "..")]` replaces the bounds.
+#[proc_macro_derive(Deserialize, attributes(serialize_as, varint, len, endian, tag, transparent, codec))]
+pub fn derive_deserialize(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    expand_deserialize(&ast).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_deserialize(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let container = Container::from_ast(ast)?;
+    let krate = &container.krate;
+    let body = decode_body(ast, &container)?;
+
+    // A transparent newtype doesn't count as a nesting level of its own
+    let decode = match container.transparent {
+        true => quote! { ::core::result::Result::Ok(#body) },
+        false => quote! {
+            ctx.enter()?;
+            let value = #body;
+            ctx.leave();
+
+            ::core::result::Result::Ok(value)
+        },
+    };
+
+    let generics = container.bounded_generics(&ast.generics, Direction::Deserialize);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::decoder::Decoder for #name #ty_generics #where_clause {
+            type Output = Self;
+
+            async fn decode_with<R: #krate::__private::AsyncRead + ::core::marker::Unpin>(
+                reader: &mut R,
+                ctx: &mut #krate::context::DecodeContext,
+            ) -> ::core::result::Result<Self::Output, #krate::errors::decode::DecodeError> {
+                #decode
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(Networked, attributes(packet_id, requires, serialize_as, varint, len, endian, tag, codec))]
 pub fn derive_networked(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
-    let struct_name = &ast.ident;
-    let attributes = &ast.attrs;
+    expand_networked(&ast).unwrap_or_else(Error::into_compile_error).into()
+}
 
-    let attribute = attributes
-        .iter()
-        .filter(|a| a.path().is_ident("packet_id"))
-        .nth(0)
-        .expect("Expected a single numeric literal (#[packet_id(0x00)]");
-
-    let packet_id: u8 = {
-        let lit: &syn::LitInt = &attribute.parse_args().unwrap();
-        let n: u8 = lit.base10_parse().expect("Expected a single numeric literal");
-        n
+fn expand_networked(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let container = Container::from_ast(ast)?;
+    let krate = &container.krate;
+    let packet_id = packet_id(ast, krate)?;
+    let requires = required_capabilities(ast, krate)?.map(|capabilities| {
+        quote! {
+            const REQUIRES: #krate::capabilities::Capabilities = #capabilities;
+        }
+    });
+
+    let encode = encode_body(ast, &container)?;
+    let decode = decode_body(ast, &container)?;
+
+    let encode_generics = container.bounded_generics(&ast.generics, Direction::Serialize);
+    let (encode_impl_generics, ty_generics, encode_where_clause) = encode_generics.split_for_impl();
+    let decode_generics = container.bounded_generics(&ast.generics, Direction::Deserialize);
+    let (decode_impl_generics, _, decode_where_clause) = decode_generics.split_for_impl();
+
+    let packet_id = packet_id.map(|packet_id| {
+        let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+        quote! {
+            impl #impl_generics #krate::messages::PacketId for #name #ty_generics #where_clause {
+                const PACKET_ID: u16 = #packet_id;
+            }
+        }
+    });
+
+    Ok(quote! {
+        #packet_id
+
+        impl #encode_impl_generics #krate::messages::SystemPacket for #name #ty_generics #encode_where_clause {
+            #requires
+
+            async fn to_bytes_with(
+                &self,
+                ctx: &#krate::context::EncodeContext,
+            ) -> ::core::result::Result<::std::vec::Vec<u8>, #krate::errors::encode::EncodeError> {
+                let mut buffer = ::std::vec::Vec::new();
+                let writer = &mut buffer;
+                #encode
+                let buffer = #krate::utils::prepare_response(<Self as #krate::messages::PacketId>::PACKET_ID, buffer).await?;
+                ::core::result::Result::Ok(buffer)
+            }
+        }
+
+        impl #decode_impl_generics #krate::decoder::ReceiveFromStream for #name #ty_generics #decode_where_clause {
+            async fn from_bytes_with(
+                buffer: &mut ::std::io::Cursor<::std::vec::Vec<u8>>,
+                ctx: &mut #krate::context::DecodeContext,
+            ) -> ::core::result::Result<Self, #krate::errors::decode::DecodeError> {
+                ctx.reset();
+                let reader = buffer;
+                ::core::result::Result::Ok(#decode)
+            }
+        }
+    })
+}
+
+/// The packet type carried by every variant of a `PacketSet`, which must be a newtype variant
+fn packet_types(ast: &DeriveInput) -> Result<Vec<(&Ident, &Type)>> {
+    let Data::Enum(data_enum) = &ast.data else {
+        return Err(Error::new(
+            ast.ident.span(),
+            "a packet set must be an enum with one variant per packet",
+        ));
     };
 
-    match ast.data {
-        Data::Struct(data_struct) => match data_struct.fields {
-            Fields::Named(fields) => {
-                let field_names = fields.named.iter().map(|field| &field.ident);
+    data_enum
+        .variants
+        .iter()
+        .map(|variant| match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Ok((&variant.ident, &fields.unnamed[0].ty)),
+            _ => Err(Error::new_spanned(
+                variant,
+                "expected a variant holding a single packet, like `Ping(Ping)`",
+            )),
+        })
+        .collect()
+}
+
+/// The settings of `#[packet_set(..)]`
+#[derive(Default)]
+struct PacketSetAttrs {
+    /// `auto` or `auto = namespace`, the set assigns the packet ids in declaration order
+    /// from the start of the `PacketNamespace` variant, `Core` by default
+    auto: Option<Ident>,
+    /// `message_type = Name`, the fieldless enum generated to look packets up by id
+    message_type: Option<Ident>,
+}
 
-                let decode_fields = fields.named.iter().map(|field| {
-                    let field_name = &field.ident.clone().unwrap();
-                    let field_type = &field.ty;
+impl PacketSetAttrs {
+    fn from_ast(ast: &DeriveInput) -> Result<Self> {
+        let mut attrs = Self::default();
 
-                    quote! {
-                        #field_name: <#field_type>::decode(buffer).await?,
+        for attribute in ast.attrs.iter().filter(|a| a.path().is_ident("packet_set")) {
+            attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("auto") {
+                    attrs.auto = Some(match meta.input.peek(Token![=]) {
+                        true => namespace(&meta.value()?.parse()?)?,
+                        false => Ident::new("Core", meta.path.span()),
+                    });
+                } else if meta.path.is_ident("message_type") {
+                    attrs.message_type = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unknown packet_set attribute, expected `auto` or `message_type`"));
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(attrs)
+    }
+}
+
+/// # Information
+/// Declares the enum as the set of packets sent in one direction, with one newtype variant per packet,
+/// and implements `PacketSet` to serialize and decode whichever packet it holds plus `From` for every packet.
+/// The build fails if two packets of the set share a packet id.
+/// With `#[packet_set(auto)]` the set assigns the ids in declaration order instead, counting up from the start
+/// of the core namespace or of the one given with `#[packet_set(auto = extensions)]`,
+/// so its packets have to be marked `#[packet_id(auto)]`.
+/// `#[packet_set(message_type = Name)]` also generates a fieldless enum `Name` naming the packets, to look them up by id.
+#[proc_macro_derive(PacketSet, attributes(packet_set, codec))]
+pub fn derive_packet_set(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    expand_packet_set(&ast).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_packet_set(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let vis = &ast.vis;
+    let container = Container::from_ast(ast)?;
+    let krate = &container.krate;
+    let attrs = PacketSetAttrs::from_ast(ast)?;
+    let packets = packet_types(ast)?;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let assign_ids = match &attrs.auto {
+        Some(namespace) => {
+            if packets.len() > usize::from(u16::MAX) + 1 {
+                return Err(Error::new(name.span(), "a packet set can't hold more than 65536 packets"));
+            }
+
+            let impls = packets.iter().enumerate().map(|(offset, (_, packet))| {
+                let offset = offset as u16;
+                quote! {
+                    impl #krate::messages::PacketId for #packet {
+                        const PACKET_ID: u16 = #krate::messages::PacketNamespace::#namespace.id(#offset);
                     }
-                });
-
-                let gen = quote! {
-                    impl crate::messages::SystemPacket for #struct_name {
-                        async fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
-                            let mut buffer = vec![];
-                            #(self.#field_names.encode(&mut buffer).await?;)*
-                            let buffer = crate::utils::prepare_response(#packet_id, buffer).await?;
-                            Ok(buffer)
+                }
+            });
+            quote! { #(#impls)* }
+        }
+        None => quote! {},
+    };
+
+    // Every pair of packets gets its own assertion, so the error names the two packets sharing an id
+    let mut assertions = vec![];
+    for (i, (first_variant, first)) in packets.iter().enumerate() {
+        for (second_variant, second) in &packets[i + 1..] {
+            let message = format!("`{first_variant}` and `{second_variant}` share a packet id in `{name}`");
+            assertions.push(quote! {
+                ::core::assert!(
+                    <#first as #krate::messages::PacketId>::PACKET_ID != <#second as #krate::messages::PacketId>::PACKET_ID,
+                    #message
+                );
+            });
+        }
+    }
+
+    let variants = packets.iter().map(|(variant, _)| variant).collect::<Vec<_>>();
+    let types = packets.iter().map(|(_, packet)| packet).collect::<Vec<_>>();
+
+    let message_type = attrs.message_type.map(|message_type| {
+        quote! {
+            #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+            #vis enum #message_type {
+                #(#variants),*
+            }
+
+            impl #message_type {
+                /// The packet sent with `id`, if it belongs to the set
+                pub fn from_id(id: u16) -> ::core::option::Option<Self> {
+                    #(
+                        if id == <#types as #krate::messages::PacketId>::PACKET_ID {
+                            return ::core::option::Option::Some(Self::#variants);
                         }
+                    )*
+                    ::core::option::Option::None
+                }
+
+                /// The id the packet is sent with
+                pub fn id(self) -> u16 {
+                    match self {
+                        #(Self::#variants => <#types as #krate::messages::PacketId>::PACKET_ID),*
                     }
+                }
+            }
 
-                    impl crate::decoder::ReceiveFromStream for #struct_name {
-                        async fn from_bytes(buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<Self, crate::errors::decode::DecodeError> {
-                            Ok(Self { #(#decode_fields)* })
-                        }
+            impl #impl_generics #name #ty_generics #where_clause {
+                /// Names the packet held by the set
+                pub fn message_type(&self) -> #message_type {
+                    match self {
+                        #(Self::#variants(_) => #message_type::#variants),*
                     }
-                };
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #assign_ids
+
+        const _: () = {
+            #(#assertions)*
+        };
 
-                gen.into()
+        #message_type
+
+        impl #impl_generics #krate::messages::PacketSet for #name #ty_generics #where_clause {
+            fn packet_id(&self) -> u16 {
+                match self {
+                    #(Self::#variants(_) => <#types as #krate::messages::PacketId>::PACKET_ID),*
+                }
             }
-            _ => panic!("Expected a named field"),
-        },
-        _ => panic!("Expected a struct"),
-    }
+
+            fn required_capabilities(&self) -> #krate::capabilities::Capabilities {
+                match self {
+                    #(Self::#variants(_) => <#types as #krate::messages::SystemPacket>::REQUIRES),*
+                }
+            }
+
+            async fn to_bytes_with(
+                &self,
+                ctx: &#krate::context::EncodeContext,
+            ) -> ::core::result::Result<::std::vec::Vec<u8>, #krate::errors::encode::EncodeError> {
+                match self {
+                    #(Self::#variants(packet) => #krate::messages::SystemPacket::to_bytes_with(packet, ctx).await),*
+                }
+            }
+
+            async fn decode_from_frame_with(
+                frame: &mut ::std::io::Cursor<::std::vec::Vec<u8>>,
+                ctx: &mut #krate::context::DecodeContext,
+            ) -> ::core::result::Result<Self, #krate::errors::decode::DecodeError> {
+                let id = <#krate::varint::VarInt<u16> as #krate::decoder::Decoder>::decode_with(frame, ctx).await?.0;
+                #(
+                    if id == <#types as #krate::messages::PacketId>::PACKET_ID {
+                        let packet = <#types as #krate::decoder::ReceiveFromStream>::from_bytes_with(frame, ctx).await?;
+                        return ::core::result::Result::Ok(Self::#variants(packet));
+                    }
+                )*
+                ::core::result::Result::Err(#krate::errors::decode::DecodeError::UnknownPacket(id))
+            }
+        }
+
+        #(
+            impl #impl_generics ::core::convert::From<#types> for #name #ty_generics #where_clause {
+                fn from(packet: #types) -> Self {
+                    Self::#variants(packet)
+                }
+            }
+        )*
+    })
 }
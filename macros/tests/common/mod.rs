@@ -0,0 +1,22 @@
+// Not every test binary uses every helper
+#![allow(dead_code)]
+
+use shared::{decoder::Decoder, encoder::Encoder, errors::decode::DecodeError};
+use std::fmt::Debug;
+
+pub async fn encoded<T: Encoder + Sync>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![];
+    value.encode(&mut bytes).await.unwrap();
+    bytes
+}
+
+pub async fn decoded<T: Decoder<Output = T>>(bytes: &[u8]) -> Result<T, DecodeError> {
+    T::decode(&mut &bytes[..]).await
+}
+
+/// Encodes `value`, checks that it decodes to an equal value and returns the bytes it was sent as
+pub async fn round_trip<T: Encoder + Decoder<Output = T> + Sync + PartialEq + Debug>(value: T) -> Vec<u8> {
+    let bytes = encoded(&value).await;
+    assert_eq!(decoded::<T>(&bytes).await.unwrap(), value);
+    bytes
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::round_trip;
+use macros::{Deserialize, Networked, Serialize};
+
+/// Stands in for a crate that re-exports `shared` under another name
+mod protocol {
+    pub use ::shared as wire;
+}
+
+use protocol::wire::messages::{PacketId, SystemPacket};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[codec(crate = "crate::protocol::wire")]
+enum Status {
+    Online,
+    Away { minutes: u16 },
+}
+
+#[derive(Networked, Debug, PartialEq)]
+#[codec(crate = "crate::protocol::wire")]
+#[packet_id(application = 3)]
+struct StatusUpdate {
+    status: Status,
+}
+
+#[tokio::test]
+async fn derives_through_a_re_export() {
+    round_trip(Status::Away { minutes: 5 }).await;
+
+    let update = StatusUpdate { status: Status::Online };
+    assert_eq!(StatusUpdate::PACKET_ID, 0x103);
+    assert!(!update.to_bytes().await.unwrap().is_empty());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::{decoded, round_trip};
+use macros::{Deserialize, Serialize};
+use shared::errors::decode::DecodeError;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Tagged {
+    #[tag(5)]
+    Unit,
+    Tuple(u8, bool),
+    #[tag(10)]
+    Struct {
+        id: u16,
+        name: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Discriminants {
+    Low = 7,
+    High = 300,
+    Next,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[repr(i8)]
+enum Signed {
+    Negative = -2,
+    #[tag(100)]
+    Overridden = 1,
+}
+
+#[tokio::test]
+async fn round_trips_tagged_variants() {
+    assert_eq!(round_trip(Tagged::Unit).await, [5]);
+    // Without a tag the variant counts up from the previous one
+    assert_eq!(round_trip(Tagged::Tuple(1, true)).await, [6, 1, 1]);
+    assert_eq!(
+        round_trip(Tagged::Struct {
+            id: 0x0102,
+            name: "a".into()
+        })
+        .await,
+        [10, 1, 2, 0, 0, 0, 1, b'a']
+    );
+}
+
+#[tokio::test]
+async fn round_trips_discriminants_with_the_repr_type() {
+    assert_eq!(round_trip(Discriminants::Low).await, [0, 7]);
+    assert_eq!(round_trip(Discriminants::High).await, [1, 44]);
+    assert_eq!(round_trip(Discriminants::Next).await, [1, 45]);
+
+    assert_eq!(round_trip(Signed::Negative).await, [(-2i8) as u8]);
+    assert_eq!(round_trip(Signed::Overridden).await, [100]);
+}
+
+#[tokio::test]
+async fn rejects_unknown_tags() {
+    assert!(matches!(
+        decoded::<Tagged>(&[7]).await,
+        Err(DecodeError::UnknownVariant { name: "Tagged", tag: 7 })
+    ));
+    assert!(matches!(
+        decoded::<Discriminants>(&[0, 8]).await,
+        Err(DecodeError::UnknownVariant {
+            name: "Discriminants",
+            tag: 8
+        })
+    ));
+    assert!(matches!(
+        decoded::<Signed>(&[1]).await,
+        Err(DecodeError::UnknownVariant { name: "Signed", tag: 1 })
+    ));
+}
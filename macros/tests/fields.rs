@@ -0,0 +1,74 @@
+mod common;
+
+use common::{decoded, encoded, round_trip};
+use macros::{Deserialize, Serialize};
+
+/// Sends a `String` as its length in a single byte followed by the bytes
+mod short_string {
+    use shared::{
+        __private::{AsyncRead, AsyncWrite},
+        context::{DecodeContext, EncodeContext},
+        decoder::DecodePrefixed,
+        encoder::EncodePrefixed,
+        errors::{decode::DecodeError, encode::EncodeError},
+        prefix::LengthPrefix,
+    };
+
+    pub async fn encode<W: AsyncWrite + Unpin + Send>(value: &String, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        value.encode_prefixed(writer, LengthPrefix::U8, ctx).await
+    }
+
+    pub async fn decode<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<String, DecodeError> {
+        String::decode_prefixed(reader, LengthPrefix::U8, ctx).await
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Profile {
+    #[codec(skip)]
+    cached: bool,
+    #[codec(skip, default = 30)]
+    timeout: u32,
+    #[codec(with = "short_string")]
+    name: String,
+    has_avatar: bool,
+    #[codec(if = "has_avatar", default = u16::MAX)]
+    avatar: u16,
+    #[codec(if = "!name.is_empty()")]
+    greeting: Option<String>,
+    trailer: u8,
+}
+
+fn profile(has_avatar: bool, name: &str) -> Profile {
+    Profile {
+        cached: false,
+        timeout: 30,
+        name: name.into(),
+        has_avatar,
+        avatar: if has_avatar { 7 } else { u16::MAX },
+        greeting: (!name.is_empty()).then(|| "hi".into()),
+        trailer: 9,
+    }
+}
+
+#[tokio::test]
+async fn round_trips_skip_default_with_and_if_together() {
+    assert_eq!(
+        round_trip(profile(true, "ab")).await,
+        [2, b'a', b'b', 1, 0, 7, 1, 0, 0, 0, 2, b'h', b'i', 9]
+    );
+    assert_eq!(round_trip(profile(false, "")).await, [0, 0, 9]);
+}
+
+#[tokio::test]
+async fn fills_skipped_and_absent_fields_with_their_defaults() {
+    let sent = Profile {
+        cached: true,
+        timeout: 1,
+        avatar: 7,
+        ..profile(false, "")
+    };
+
+    let received = decoded::<Profile>(&encoded(&sent).await).await.unwrap();
+    assert_eq!(received, profile(false, ""));
+}
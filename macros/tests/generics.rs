@@ -0,0 +1,73 @@
+mod common;
+
+use common::{encoded, round_trip};
+use macros::{Deserialize, Serialize};
+use std::{borrow::Cow, marker::PhantomData};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Borrowed<'a, T> {
+    name: Cow<'a, str>,
+    value: T,
+    values: Vec<T>,
+}
+
+/// A unit that only tells how its values are sent, it isn't sent itself
+#[derive(Debug, PartialEq)]
+struct Meters;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[codec(bound = "U: Sync")]
+struct Length<U> {
+    value: u32,
+    unit: PhantomData<U>,
+}
+
+trait Wire {
+    type Repr;
+}
+
+#[derive(Debug, PartialEq)]
+struct Celsius;
+
+impl Wire for Celsius {
+    type Repr = i16;
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[codec(bound(
+    serialize = "T::Repr: shared::encoder::Encoder + Sync",
+    deserialize = "T::Repr: shared::decoder::Decoder<Output = T::Repr>"
+))]
+struct Reading<T: Wire> {
+    value: T::Repr,
+}
+
+#[tokio::test]
+async fn round_trips_lifetimes_and_type_parameters() {
+    let borrowed = Borrowed {
+        name: Cow::Borrowed("sensor"),
+        value: 1u8,
+        values: vec![2, 3],
+    };
+    assert_eq!(round_trip(borrowed).await.len(), 4 + 6 + 1 + 4 + 2);
+
+    round_trip(Borrowed {
+        name: Cow::Owned(String::new()),
+        value: Some(-1i64),
+        values: vec![None],
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn replaces_the_bounds_with_codec_bound() {
+    // `Meters` has no codec, the bound replaces the `Encoder` and `Decoder` bounds the derive would add
+    let length = Length::<Meters> {
+        value: 5,
+        unit: PhantomData,
+    };
+    assert_eq!(round_trip(length).await, encoded(&5u32).await);
+
+    let reading = Reading::<Celsius> { value: -40 };
+    assert_eq!(round_trip(reading).await, encoded(&-40i16).await);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{encoded, round_trip};
+use macros::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Pair(u16, String);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Newtype(u32);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[transparent]
+struct UserId(u64);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Unit;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Nested {
+    pair: Pair,
+    id: UserId,
+    unit: Unit,
+}
+
+#[tokio::test]
+async fn round_trips_tuple_structs() {
+    assert_eq!(round_trip(Pair(0x0102, "ab".into())).await, [1, 2, 0, 0, 0, 2, b'a', b'b']);
+    assert_eq!(round_trip(Newtype(7)).await, [0, 0, 0, 7]);
+}
+
+#[tokio::test]
+async fn writes_transparent_newtypes_like_their_field() {
+    assert_eq!(round_trip(UserId(7)).await, encoded(&7u64).await);
+}
+
+#[tokio::test]
+async fn writes_unit_structs_as_nothing() {
+    assert!(round_trip(Unit).await.is_empty());
+
+    let nested = Nested {
+        pair: Pair(1, String::new()),
+        id: UserId(2),
+        unit: Unit,
+    };
+    assert_eq!(round_trip(nested).await.len(), 2 + 4 + 8);
+}
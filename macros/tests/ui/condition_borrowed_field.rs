@@ -0,0 +1,18 @@
+use macros::Serialize;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+enum Kind {
+    Statement,
+    Question,
+}
+
+#[derive(Serialize)]
+struct Message {
+    has_reply: bool,
+    kind: Kind,
+    // Primitives are bound by value, every other field by reference
+    #[codec(if = "has_reply && kind == Kind::Question")]
+    reply_to: u32,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use macros::Deserialize;
+
+#[derive(Deserialize)]
+struct Chained {
+    present: bool,
+    #[codec(if = "present")]
+    flag: bool,
+    #[codec(if = "flag")]
+    value: u32,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+struct Cached {
+    #[codec(skip)]
+    cached: bool,
+    #[codec(if = "cached")]
+    value: u32,
+    trailer: u8,
+}
+
+fn main() {}
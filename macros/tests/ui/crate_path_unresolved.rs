@@ -0,0 +1,9 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+#[codec(crate = "missing")]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
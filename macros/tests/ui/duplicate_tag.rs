@@ -0,0 +1,10 @@
+use macros::Deserialize;
+
+#[derive(Deserialize)]
+enum Status {
+    Ok,
+    #[tag(0)]
+    Failed,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+#[endian(middle)]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
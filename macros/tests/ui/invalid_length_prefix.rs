@@ -0,0 +1,9 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+struct Chat {
+    #[len(u64)]
+    message: String,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use macros::Networked;
+
+#[derive(Networked)]
+#[packet_id(0x01)]
+#[packet_id(0x02)]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use macros::Networked;
+
+#[derive(Networked)]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
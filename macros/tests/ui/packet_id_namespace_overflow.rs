@@ -0,0 +1,9 @@
+use macros::Networked;
+
+#[derive(Networked)]
+#[packet_id(core = 0x100)]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
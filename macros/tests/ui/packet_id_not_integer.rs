@@ -0,0 +1,9 @@
+use macros::Networked;
+
+#[derive(Networked)]
+#[packet_id("ping")]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
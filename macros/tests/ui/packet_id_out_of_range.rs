@@ -0,0 +1,9 @@
+use macros::Networked;
+
+#[derive(Networked)]
+#[packet_id(0x10000)]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
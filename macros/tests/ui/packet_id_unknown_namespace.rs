@@ -0,0 +1,9 @@
+use macros::Networked;
+
+#[derive(Networked)]
+#[packet_id(plugins = 0x01)]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use macros::{Networked, PacketSet};
+
+#[derive(Networked)]
+#[packet_id(0x01)]
+struct Ping;
+
+#[derive(Networked)]
+#[packet_id(0x01)]
+struct Pong;
+
+#[derive(PacketSet)]
+enum Packets {
+    Ping(Ping),
+    Pong(Pong),
+}
+
+fn main() {}
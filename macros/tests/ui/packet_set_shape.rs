@@ -0,0 +1,8 @@
+use macros::PacketSet;
+
+#[derive(PacketSet)]
+enum Packets {
+    Ping { timestamp: u64 },
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use macros::Networked;
+
+#[derive(Networked)]
+#[packet_id(0x01)]
+#[requires("chat")]
+struct Message {
+    text: String,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use macros::Serialize;
+
+const FAILED: u8 = 1;
+
+#[derive(Serialize)]
+#[repr(u8)]
+enum Status {
+    Ok = 0,
+    Failed = FAILED,
+}
+
+fn main() {}
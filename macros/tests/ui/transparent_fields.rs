@@ -0,0 +1,7 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+#[transparent]
+struct Pair(u8, u8);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
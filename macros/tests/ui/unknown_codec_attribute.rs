@@ -0,0 +1,9 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+struct Ping {
+    #[codec(skipp)]
+    timestamp: u64,
+}
+
+fn main() {}
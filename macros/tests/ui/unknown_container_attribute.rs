@@ -0,0 +1,9 @@
+use macros::Deserialize;
+
+#[derive(Deserialize)]
+#[codec(krate = "shared")]
+struct Ping {
+    timestamp: u64,
+}
+
+fn main() {}
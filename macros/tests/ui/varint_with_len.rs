@@ -0,0 +1,10 @@
+use macros::Serialize;
+
+#[derive(Serialize)]
+struct Ping {
+    #[varint]
+    #[len(u8)]
+    timestamp: u64,
+}
+
+fn main() {}
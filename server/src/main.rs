@@ -1,101 +1,159 @@
+use futures::{
+    stream::{SplitStream, StreamExt},
+    SinkExt,
+};
 use shared::{
-    decoder::ReceiveFromStream,
+    capabilities::Capabilities,
+    codec::PacketCodec,
+    encryption::{PublicKey, StaticSecret},
+    handshake::{self, Hello, Negotiated, PROTOCOL_VERSIONS},
     messages::{
-        client::{AuthenticationResponse, ClientMessageType, KeepAliveResponse},
+        client::ClientPackets,
         server::{AuthenticationRequest, KeepAliveRequest, ServerPackets},
-        SystemPacket,
     },
-    ADDR, PORT,
+    ADDR, PORT, SERVER_KEY_PATH, SERVER_PUBLIC_KEY_PATH,
 };
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::{
-    io::{self, Cursor},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
     net::SocketAddr,
     time::Duration,
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{tcp::OwnedReadHalf, TcpListener},
+    net::{TcpListener, TcpStream},
     sync::mpsc::{channel, Sender},
     task::JoinSet,
 };
+use tokio_util::codec::Framed;
+
+type ServerFramed = Framed<TcpStream, PacketCodec<ClientPackets, ServerPackets>>;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
     let listener = TcpListener::bind(format!("{ADDR}:{PORT}")).await?;
     const KEEP_ALIVE_INTERVAL: u64 = 15;
+    const CAPABILITIES: Capabilities = Capabilities::CHAT.union(Capabilities::COMPRESSION).union(Capabilities::ENCRYPTION);
+    let identity = load_identity()?;
 
     loop {
-        let (stream, addr) = listener.accept().await?;
-        let (reader, mut writer) = stream.into_split();
+        let (mut stream, addr) = listener.accept().await?;
+        let hello = Hello::new(PROTOCOL_VERSIONS, CAPABILITIES);
+        let mut negotiated = match handshake::accept(&mut stream, hello, Some(&identity)).await {
+            Ok(negotiated) => negotiated,
+            Err(why) => {
+                println!("> {} failed the handshake: {}", addr, why);
+                continue;
+            }
+        };
+
+        let (mut sink, stream) = Framed::new(
+            stream,
+            PacketCodec::default()
+                .with_capabilities(negotiated.capabilities)
+                .with_compression(negotiated.compression)
+                .with_session(negotiated.session.take()),
+        )
+        .split();
         let (sender, mut receiver) = channel::<ServerPackets>(100);
         let keep_alive_sender = sender.clone();
 
         let mut set = JoinSet::new();
 
-        set.spawn(async move { handle_client(addr, reader, sender).await });
+        set.spawn(async move { handle_client(addr, negotiated, stream, sender).await });
         set.spawn(async move { keep_alive(keep_alive_sender, KEEP_ALIVE_INTERVAL).await });
         set.spawn(async move {
             while let Some(recv) = receiver.recv().await {
-                let buffer = match recv {
-                    ServerPackets::AuthenticationRequest(x) => x.to_bytes().await,
-                    ServerPackets::KeepAliveRequest(x) => x.to_bytes().await,
-                }
-                .unwrap();
-
-                writer.write_all(&buffer[..]).await.unwrap();
+                sink.send(recv).await.unwrap();
             }
 
             Ok(())
         });
 
-        while let Some(_) = set.join_next().await {
-            drop(set);
-            break;
-        }
+        set.join_next().await;
+        drop(set);
 
         // At this point the client is not connected anymore!
     }
 }
 
-async fn handle_client(addr: SocketAddr, mut reader: OwnedReadHalf, sender: Sender<ServerPackets>) -> io::Result<()> {
-    sender
-        .send(ServerPackets::AuthenticationRequest(AuthenticationRequest::new()))
-        .await
-        .unwrap();
+async fn handle_client(
+    addr: SocketAddr,
+    negotiated: Negotiated,
+    mut stream: SplitStream<ServerFramed>,
+    sender: Sender<ServerPackets>,
+) -> io::Result<()> {
+    println!(
+        "> {} connected with protocol version {} and capabilities {}",
+        addr, negotiated.version, negotiated.capabilities
+    );
+    sender.send(AuthenticationRequest::new().into()).await.unwrap();
+
+    while let Some(packet) = stream.next().await {
+        match packet {
+            Ok(ClientPackets::AuthenticationResponse(res)) => {
+                println!("{res:?}");
+            }
 
-    loop {
-        let len = reader.read_i32().await.unwrap_or(0) as usize;
-        if len == 0 {
-            println!("> {} disconnected", addr);
-            break;
-        } // TODO Read chunks if more than 1024 bytes!!
-
-        let mut buffer = vec![0u8; len];
-        match reader.read_exact(&mut buffer).await {
-            Ok(read) => {
-                if read == 0 {
-                    println!("> {} disconnected", addr);
-                    break;
-                }
-
-                let mut cursor = Cursor::new(buffer);
-                match ClientMessageType::from(&mut cursor).await {
-                    ClientMessageType::AuthenticationResponse => {
-                        let res = AuthenticationResponse::from_bytes(&mut cursor).await.unwrap();
-                        println!("{res:?}");
-                    }
-
-                    ClientMessageType::KeepAliveResponse => {
-                        let res = KeepAliveResponse::from_bytes(&mut cursor).await.unwrap();
-                        println!("{res:?}");
-                    }
-                    _ => panic!("Received invalid packet"),
-                }
+            Ok(ClientPackets::KeepAliveResponse(res)) => {
+                println!("{res:?}");
+            }
+            Err(why) => {
+                println!("> {} sent an invalid packet, disconnecting: {}", addr, why);
+                return Ok(());
             }
-            Err(why) => panic!("{why}"),
         }
     }
 
+    println!("> {} disconnected", addr);
+    Ok(())
+}
+
+/// Reads the static secret of the server, or creates it along with the public key for the clients to pin.
+/// Only the owner may access the secret, a key others can read is refused.
+fn load_identity() -> io::Result<StaticSecret> {
+    match File::open(SERVER_KEY_PATH) {
+        Ok(mut file) => {
+            check_private(&file)?;
+
+            let mut bytes = vec![];
+            file.read_to_end(&mut bytes)?;
+            <[u8; 32]>::try_from(bytes)
+                .map(StaticSecret::from)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{SERVER_KEY_PATH} has to hold 32 bytes")))
+        }
+        Err(why) if why.kind() == io::ErrorKind::NotFound => {
+            let identity = StaticSecret::random();
+
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            options.open(SERVER_KEY_PATH)?.write_all(identity.as_bytes())?;
+
+            fs::write(SERVER_PUBLIC_KEY_PATH, PublicKey::from(&identity).as_bytes())?;
+            println!("Created a new server key, clients have to pin {SERVER_PUBLIC_KEY_PATH}");
+            Ok(identity)
+        }
+        Err(why) => Err(why),
+    }
+}
+
+#[cfg(unix)]
+fn check_private(file: &File) -> io::Result<()> {
+    let mode = file.metadata()?.permissions().mode() & 0o777;
+    match mode & 0o077 {
+        0 => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{SERVER_KEY_PATH} is accessible by other users (mode {mode:o}), restrict it with `chmod 600 {SERVER_KEY_PATH}`"),
+        )),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_private(_file: &File) -> io::Result<()> {
     Ok(())
 }
 
@@ -106,7 +164,7 @@ async fn keep_alive(sender: Sender<ServerPackets>, interval: u64) -> io::Result<
 
     loop {
         interval_timer.tick().await;
-        let packet = ServerPackets::KeepAliveRequest(KeepAliveRequest::new());
+        let packet = ServerPackets::from(KeepAliveRequest::new());
         println!("KeepAliveRequest sent... {:?}", packet);
         sender.send(packet).await.unwrap();
     }
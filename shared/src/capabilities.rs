@@ -0,0 +1,83 @@
+use macros::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::{BitAnd, BitOr},
+};
+
+/// # Information
+/// The optional features one side of a connection supports, as a set of flags exchanged during the handshake.
+/// The connection keeps the intersection of both sides, bits unknown to this build are dropped along the way.
+/// Packets declare what they need with `#[requires(CHAT)]`, sending one the peer lacks fails before it is written.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[transparent]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    pub const COMPRESSION: Self = Self(1 << 0);
+    pub const ENCRYPTION: Self = Self(1 << 1);
+    pub const CHAT: Self = Self(1 << 2);
+    pub const ALL: Self = Self(Self::COMPRESSION.0 | Self::ENCRYPTION.0 | Self::CHAT.0);
+
+    const NAMES: [(Self, &'static str); 3] = [
+        (Self::COMPRESSION, "COMPRESSION"),
+        (Self::ENCRYPTION, "ENCRYPTION"),
+        (Self::CHAT, "CHAT"),
+    ];
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// The flags of `self` that `other` lacks
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        self.intersection(rhs)
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+
+        match names.is_empty() {
+            true => write!(f, "NONE"),
+            false => write!(f, "{}", names.join(" | ")),
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    capabilities::Capabilities,
+    compression::{Compression, CompressionSettings},
+    context::{DecodeContext, EncodeContext},
+    encryption::SessionKeys,
+    errors::{decode::DecodeError, encode::EncodeError},
+    messages::PacketSet,
+    utils::{self, FRAME_HEADER_LEN},
+};
+use std::{
+    future::Future,
+    io::{self, Cursor},
+    marker::PhantomData,
+    mem::size_of,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+use tokio_util::{
+    bytes::{Buf, BufMut, BytesMut},
+    codec::{Decoder, Encoder},
+};
+
+/// # Information
+/// The framing of `prepare_response` as a `tokio_util` codec, reading `Incoming` packets and writing `Outgoing` ones.
+/// Wrapping a `TcpStream` in `Framed` with it gives a `Stream` and `Sink` of typed packets.
+/// Frames longer than `max_bytes` of the decode limits are rejected before they are buffered.
+/// Packets requiring capabilities outside of `capabilities` are rejected before they are written.
+/// Payloads above the threshold of `compression` are compressed when that makes them smaller,
+/// and compressed frames may decompress to at most `max_decompressed` of the decode limits.
+/// With a `session` everything but the length of a frame is sealed, after compressing it.
+///
+/// Frames are encoded and decoded in memory, polling each codec future exactly once.
+/// Codecs used by the packets, `#[codec(with = "..")]` modules included, must not await anything but the buffer they
+/// read from or write to, otherwise the frame fails with `io::ErrorKind::WouldBlock`.
+pub struct PacketCodec<Incoming, Outgoing> {
+    encode: EncodeContext,
+    decode: DecodeContext,
+    capabilities: Capabilities,
+    compression: CompressionSettings,
+    session: Option<SessionKeys>,
+    packets: PhantomData<fn(Outgoing) -> Incoming>,
+}
+
+impl<Incoming, Outgoing> PacketCodec<Incoming, Outgoing> {
+    pub fn new(encode: EncodeContext, decode: DecodeContext) -> Self {
+        Self {
+            encode,
+            decode,
+            capabilities: Capabilities::ALL,
+            compression: CompressionSettings::default(),
+            session: None,
+            packets: PhantomData,
+        }
+    }
+
+    /// Compresses outgoing frames as negotiated with the peer, incoming frames are decompressed regardless
+    pub fn with_compression(mut self, compression: CompressionSettings) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Seals and opens every frame with the keys negotiated during the handshake
+    pub fn with_session(mut self, session: Option<SessionKeys>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Limits the packets that may be sent to the capabilities negotiated with the peer
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+}
+
+impl<Incoming, Outgoing> Default for PacketCodec<Incoming, Outgoing> {
+    fn default() -> Self {
+        Self::new(EncodeContext::default(), DecodeContext::default())
+    }
+}
+
+impl<Incoming: PacketSet, Outgoing> Decoder for PacketCodec<Incoming, Outgoing> {
+    type Item = Incoming;
+    type Error = DecodeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(len) = src.get(..size_of::<u32>()) else {
+            return Ok(None);
+        };
+
+        let len = u32::from_be_bytes(len.try_into().expect("slice has the length of a u32")) as usize;
+        if len > self.decode.limits.max_bytes {
+            return Err(DecodeError::FrameTooLong {
+                len,
+                max: self.decode.limits.max_bytes,
+            });
+        }
+
+        if src.len() < size_of::<u32>() + len {
+            src.reserve(size_of::<u32>() + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(size_of::<u32>());
+        let frame = src.split_to(len);
+        let frame = match &mut self.session {
+            Some(session) => session.open(&frame)?,
+            None => frame.to_vec(),
+        };
+
+        let Some((&flags, payload)) = frame.split_first() else {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        };
+
+        let compression = Compression::from_flags(flags)?;
+        let mut frame = Cursor::new(compression.decompress(payload, self.decode.limits.max_decompressed)?);
+
+        let packet = complete(Incoming::decode_from_frame_with(&mut frame, &mut self.decode))??;
+        Ok(Some(packet))
+    }
+}
+
+impl<Incoming, Outgoing: PacketSet> Encoder<Outgoing> for PacketCodec<Incoming, Outgoing> {
+    type Error = EncodeError;
+
+    fn encode(&mut self, item: Outgoing, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let missing = item.required_capabilities().difference(self.capabilities);
+        if !missing.is_empty() {
+            return Err(EncodeError::MissingCapabilities {
+                packet: item.packet_id(),
+                missing,
+            });
+        }
+
+        let mut buffer = complete(item.to_bytes_with(&self.encode))??;
+        let payload = &buffer[FRAME_HEADER_LEN..];
+
+        if self.compression.algorithm != Compression::None && payload.len() > self.compression.threshold as usize {
+            let compressed = self.compression.algorithm.compress(payload)?;
+            if compressed.len() < payload.len() {
+                buffer = complete(utils::frame(self.compression.algorithm, compressed))??;
+            }
+        }
+
+        match &mut self.session {
+            Some(session) => {
+                let sealed = session.seal(&buffer[size_of::<u32>()..])?;
+                dst.put_u32(u32::try_from(sealed.len())?);
+                dst.extend_from_slice(&sealed);
+            }
+            None => dst.extend_from_slice(&buffer),
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs an encode or decode future to completion in one poll.
+/// They only read from and write to memory, so they never have to wait.
+fn complete<F: Future>(future: F) -> io::Result<F::Output> {
+    match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => Ok(output),
+        Poll::Pending => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "packet codec future waited on something other than its buffer, codecs of packets must not await I/O or timers",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DecodeContext;
+    use macros::{Networked, PacketSet};
+    use std::future::poll_fn;
+    use tokio::io::{AsyncRead, AsyncWrite};
+
+    /// Awaits something other than the buffer, as a codec doing I/O would
+    mod pending {
+        use super::*;
+
+        async fn wait_once() {
+            let mut waited = false;
+            poll_fn(|_| match waited {
+                true => Poll::Ready(()),
+                false => {
+                    waited = true;
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+
+        pub async fn encode<W: AsyncWrite + Unpin + Send>(_: &u8, _: &mut W, _: &EncodeContext) -> Result<(), EncodeError> {
+            wait_once().await;
+            Ok(())
+        }
+
+        pub async fn decode<R: AsyncRead + Unpin>(_: &mut R, _: &mut DecodeContext) -> Result<u8, DecodeError> {
+            wait_once().await;
+            Ok(0)
+        }
+    }
+
+    #[derive(Networked, Debug)]
+    #[packet_id(application = 0)]
+    struct Waiting {
+        #[codec(with = "pending")]
+        value: u8,
+    }
+
+    #[derive(Debug, PacketSet)]
+    enum Packets {
+        Waiting(Waiting),
+    }
+
+    #[derive(Networked, Debug)]
+    #[packet_id(application = 1)]
+    #[requires(CHAT)]
+    struct Chat {
+        text: String,
+    }
+
+    #[derive(Debug, PacketSet)]
+    enum ChatPackets {
+        Chat(Chat),
+    }
+
+    #[test]
+    fn refuses_packets_the_peer_lacks_capabilities_for() {
+        let mut codec = PacketCodec::<ChatPackets, ChatPackets>::default().with_capabilities(Capabilities::COMPRESSION);
+
+        let mut dst = BytesMut::new();
+        let result = codec.encode(Chat { text: "hi".into() }.into(), &mut dst);
+        assert!(matches!(
+            result,
+            Err(EncodeError::MissingCapabilities { packet: 0x101, missing }) if missing == Capabilities::CHAT
+        ));
+        assert!(dst.is_empty());
+
+        let mut codec = codec.with_capabilities(Capabilities::CHAT);
+        codec.encode(Chat { text: "hi".into() }.into(), &mut dst).unwrap();
+        assert!(matches!(codec.decode(&mut dst), Ok(Some(ChatPackets::Chat(chat))) if chat.text == "hi"));
+    }
+
+    fn would_block(error: &io::Error) -> bool {
+        error.kind() == io::ErrorKind::WouldBlock
+    }
+
+    #[test]
+    fn rejects_codecs_that_await() {
+        let mut codec = PacketCodec::<Packets, Packets>::default();
+
+        let mut dst = BytesMut::new();
+        let result = codec.encode(Waiting { value: 0 }.into(), &mut dst);
+        assert!(matches!(result, Err(EncodeError::IO(ref error)) if would_block(error)));
+        assert!(dst.is_empty());
+
+        // The id of the packet followed by its byte, behind the length and flags of the frame
+        let mut src = BytesMut::from(&[0, 0, 0, 4, 0, 0x80, 0x02, 0][..]);
+        let result = codec.decode(&mut src);
+        assert!(matches!(result, Err(DecodeError::IO(ref error)) if would_block(error)));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::errors::{decode::DecodeError, encode::EncodeError};
+use macros::{Deserialize, Serialize};
+
+/// Frames with a smaller payload are sent as is unless the peers agree on another threshold
+pub const DEFAULT_THRESHOLD: u32 = 512;
+
+/// # Information
+/// The algorithm a frame payload is compressed with, written to the flags byte of the frame header.
+/// Every algorithm has a fixed value on the wire, but only those enabled with the `zstd` and `deflate` features can be used.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Zstd = 1,
+    Deflate = 2,
+}
+
+impl Compression {
+    /// The algorithms compiled into this build, the most preferred first
+    pub const SUPPORTED: &'static [Self] = &[
+        #[cfg(feature = "zstd")]
+        Self::Zstd,
+        #[cfg(feature = "deflate")]
+        Self::Deflate,
+    ];
+
+    /// The algorithm of the flags byte of a frame header
+    pub fn from_flags(flags: u8) -> Result<Self, DecodeError> {
+        match flags {
+            0 => Ok(Self::None),
+            1 if Self::SUPPORTED.contains(&Self::Zstd) => Ok(Self::Zstd),
+            2 if Self::SUPPORTED.contains(&Self::Deflate) => Ok(Self::Deflate),
+            _ => Err(DecodeError::UnknownCompression(flags)),
+        }
+    }
+
+    pub fn compress(self, payload: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            Self::None => Ok(payload.to_vec()),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(zstd::stream::encode_all(payload, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            #[cfg(feature = "deflate")]
+            Self::Deflate => {
+                use std::io::Write;
+
+                let mut encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(payload)?;
+                Ok(encoder.finish()?)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(EncodeError::UnsupportedCompression(self)),
+        }
+    }
+
+    /// Decompresses `payload`, failing as soon as the output would grow past `max` bytes
+    pub fn decompress(self, payload: &[u8], max: usize) -> Result<Vec<u8>, DecodeError> {
+        match self {
+            Self::None => read_limited(payload, max),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => read_limited(zstd::stream::read::Decoder::new(payload)?, max),
+            #[cfg(feature = "deflate")]
+            Self::Deflate => read_limited(flate2::read::DeflateDecoder::new(payload), max),
+            #[allow(unreachable_patterns)]
+            _ => Err(DecodeError::UnknownCompression(self as u8)),
+        }
+    }
+}
+
+/// Reads at most one byte past `max`, so a zip bomb is caught without inflating it
+fn read_limited<R: std::io::Read>(reader: R, max: usize) -> Result<Vec<u8>, DecodeError> {
+    use std::io::Read;
+
+    let mut output = vec![];
+    reader.take(max as u64 + 1).read_to_end(&mut output)?;
+
+    match output.len() > max {
+        true => Err(DecodeError::DecompressedTooLong { max }),
+        false => Ok(output),
+    }
+}
+
+/// # Information
+/// The algorithms one side of a connection can decompress and the smallest payload it wants compressed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionOffer {
+    /// One bit per `Compression` value
+    algorithms: u8,
+    pub threshold: u32,
+}
+
+impl CompressionOffer {
+    /// Offers every algorithm compiled into this build
+    pub fn supported(threshold: u32) -> Self {
+        let algorithms = Compression::SUPPORTED
+            .iter()
+            .fold(0, |bits, algorithm| bits | 1 << *algorithm as u8);
+        Self { algorithms, threshold }
+    }
+
+    pub fn contains(self, algorithm: Compression) -> bool {
+        self.algorithms & 1 << algorithm as u8 != 0
+    }
+
+    /// Picks the most preferred algorithm both sides support and the larger of both thresholds
+    pub fn negotiate(self, other: Self) -> CompressionSettings {
+        let algorithm = Compression::SUPPORTED
+            .iter()
+            .copied()
+            .find(|algorithm| self.contains(*algorithm) && other.contains(*algorithm))
+            .unwrap_or_default();
+
+        CompressionSettings {
+            algorithm,
+            threshold: self.threshold.max(other.threshold),
+        }
+    }
+}
+
+/// # Information
+/// How the frames of a connection are compressed, agreed on during the handshake.
+/// The default leaves every frame uncompressed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionSettings {
+    pub algorithm: Compression,
+    /// Payloads of at most this many bytes are sent uncompressed
+    pub threshold: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(algorithms: &[Compression], threshold: u32) -> CompressionOffer {
+        let algorithms = algorithms.iter().fold(0, |bits, algorithm| bits | 1 << *algorithm as u8);
+        CompressionOffer { algorithms, threshold }
+    }
+
+    #[test]
+    fn negotiates_nothing_without_a_common_algorithm() {
+        let settings = offer(&[Compression::Zstd], 128).negotiate(offer(&[Compression::Deflate], 1024));
+        assert_eq!(settings.algorithm, Compression::None);
+        assert_eq!(settings.threshold, 1024);
+
+        let settings = offer(&[], 0).negotiate(CompressionOffer::supported(DEFAULT_THRESHOLD));
+        assert_eq!(settings.algorithm, Compression::None);
+    }
+
+    #[cfg(all(feature = "zstd", feature = "deflate"))]
+    #[test]
+    fn negotiates_the_most_preferred_common_algorithm() {
+        let both = offer(&[Compression::Zstd, Compression::Deflate], DEFAULT_THRESHOLD);
+
+        assert_eq!(both.negotiate(both).algorithm, Compression::Zstd);
+        assert_eq!(both.negotiate(offer(&[Compression::Deflate], 0)).algorithm, Compression::Deflate);
+        assert_eq!(offer(&[Compression::Zstd], 0).negotiate(both).algorithm, Compression::Zstd);
+    }
+
+    #[test]
+    fn rejects_unknown_algorithms() {
+        assert!(matches!(Compression::from_flags(0), Ok(Compression::None)));
+        assert!(matches!(Compression::from_flags(3), Err(DecodeError::UnknownCompression(3))));
+    }
+
+    #[cfg(any(feature = "zstd", feature = "deflate"))]
+    mod algorithms {
+        use super::*;
+        use crate::{
+            codec::PacketCodec,
+            context::{DecodeLimits, EncodeContext},
+            messages::client::{AuthenticationResponse, ClientPackets},
+            types::Hwid,
+            utils::FRAME_HEADER_LEN,
+        };
+        use tokio_util::{
+            bytes::BytesMut,
+            codec::{Decoder, Encoder},
+        };
+
+        fn round_trip(algorithm: Compression) {
+            let payload = b"a payload that repeats itself, ".repeat(64);
+
+            let compressed = algorithm.compress(&payload).unwrap();
+            assert!(compressed.len() < payload.len());
+            assert_eq!(algorithm.decompress(&compressed, payload.len()).unwrap(), payload);
+        }
+
+        fn rejects_bombs(algorithm: Compression) {
+            let bomb = algorithm.compress(&vec![0; 16 * 1024 * 1024]).unwrap();
+            assert!(bomb.len() < 64 * 1024);
+
+            let result = algorithm.decompress(&bomb, 1024 * 1024);
+            assert!(matches!(result, Err(DecodeError::DecompressedTooLong { max: 0x10_0000 })));
+        }
+
+        fn packet(nonce_len: usize) -> ClientPackets {
+            AuthenticationResponse {
+                hwid: Hwid {
+                    cpu_id: String::new(),
+                    system_id: String::new(),
+                },
+                nonce: "n".repeat(nonce_len),
+            }
+            .into()
+        }
+
+        /// Encodes `packet` and returns the flags byte of the frame after decoding it again
+        fn send(algorithm: Compression, packet: ClientPackets) -> u8 {
+            let settings = CompressionSettings { algorithm, threshold: 512 };
+            let mut codec = PacketCodec::<ClientPackets, ClientPackets>::default().with_compression(settings);
+
+            let mut frame = BytesMut::new();
+            codec.encode(packet, &mut frame).unwrap();
+            let flags = frame[FRAME_HEADER_LEN - 1];
+
+            assert!(matches!(
+                codec.decode(&mut frame),
+                Ok(Some(ClientPackets::AuthenticationResponse(_)))
+            ));
+            flags
+        }
+
+        fn compresses_above_the_threshold(algorithm: Compression) {
+            assert_eq!(send(algorithm, packet(16)), Compression::None as u8);
+            assert_eq!(send(algorithm, packet(4096)), algorithm as u8);
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn zstd() {
+            round_trip(Compression::Zstd);
+            rejects_bombs(Compression::Zstd);
+            compresses_above_the_threshold(Compression::Zstd);
+        }
+
+        #[cfg(feature = "deflate")]
+        #[test]
+        fn deflate() {
+            round_trip(Compression::Deflate);
+            rejects_bombs(Compression::Deflate);
+            compresses_above_the_threshold(Compression::Deflate);
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn limits_decompressed_frames_separately() {
+            let limits = DecodeLimits {
+                max_decompressed: 1024,
+                ..Default::default()
+            };
+            let decode = crate::context::DecodeContext::default().with_limits(limits);
+            let settings = CompressionSettings {
+                algorithm: Compression::Zstd,
+                threshold: 0,
+            };
+
+            let mut frame = BytesMut::new();
+            PacketCodec::<ClientPackets, ClientPackets>::default()
+                .with_compression(settings)
+                .encode(packet(4096), &mut frame)
+                .unwrap();
+
+            let mut codec = PacketCodec::<ClientPackets, ClientPackets>::new(EncodeContext::default(), decode);
+            assert!(matches!(
+                codec.decode(&mut frame),
+                Err(DecodeError::DecompressedTooLong { max: 1024 })
+            ));
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::errors::decode::DecodeError;
+
+/// # Information
+/// Byte order used for multi-byte integers, floats and length prefixes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Endian {
+    #[default]
+    Big,
+    Little,
+}
+
+/// # Information
+/// Settings handed down through every `Encoder`.
+/// The default is the codec-wide default, `#[endian(..)]` on a struct or field overrides it for that part of the value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodeContext {
+    pub endian: Endian,
+}
+
+impl EncodeContext {
+    pub fn new(endian: Endian) -> Self {
+        Self { endian }
+    }
+
+    /// Copy of the context with another byte order, used for `#[endian(..)]` overrides
+    pub fn with_endian(&self, endian: Endian) -> Self {
+        let mut ctx = *self;
+        ctx.endian = endian;
+        ctx
+    }
+}
+
+/// # Information
+/// Upper bounds applied while decoding, so a peer can't make us allocate arbitrary amounts of memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Longest string or byte array in bytes
+    pub max_string_length: usize,
+    /// Most elements (or entries) in a single collection
+    pub max_elements: usize,
+    /// Deepest nesting of structs, collections and pointers
+    pub max_depth: usize,
+    /// Bytes a single packet may make the decoder allocate, also the largest frame that is read at all
+    pub max_bytes: usize,
+    /// Largest payload a compressed frame may decompress to, checked separately from the frame itself
+    pub max_decompressed: usize,
+}
+
+impl DecodeLimits {
+    /// Disables every limit, only meant for trusted input
+    pub const UNLIMITED: Self = Self {
+        max_string_length: usize::MAX,
+        max_elements: usize::MAX,
+        max_depth: usize::MAX,
+        max_bytes: usize::MAX,
+        max_decompressed: usize::MAX,
+    };
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_string_length: 64 * 1024,
+            max_elements: 64 * 1024,
+            max_depth: 32,
+            max_bytes: 1024 * 1024,
+            max_decompressed: 1024 * 1024,
+        }
+    }
+}
+
+/// # Information
+/// State handed down through every `Decoder`, mirrors `EncodeContext` and keeps track of the `DecodeLimits`.
+/// The depth and byte counters are per packet, `reset` is called before each packet is decoded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeContext {
+    pub endian: Endian,
+    pub limits: DecodeLimits,
+    depth: usize,
+    allocated: usize,
+}
+
+impl DecodeContext {
+    pub fn new(endian: Endian) -> Self {
+        Self {
+            endian,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Clears the per packet counters
+    pub fn reset(&mut self) {
+        self.depth = 0;
+        self.allocated = 0;
+    }
+
+    pub fn check_string_length(&self, len: usize) -> Result<(), DecodeError> {
+        match len > self.limits.max_string_length {
+            true => Err(DecodeError::StringTooLong {
+                len,
+                max: self.limits.max_string_length,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    pub fn check_elements(&self, len: usize) -> Result<(), DecodeError> {
+        match len > self.limits.max_elements {
+            true => Err(DecodeError::TooManyElements {
+                len,
+                max: self.limits.max_elements,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Charges `bytes` against the budget of the current packet, has to be called before allocating them
+    pub fn allocate(&mut self, bytes: usize) -> Result<(), DecodeError> {
+        match self.allocated.checked_add(bytes) {
+            Some(total) if total <= self.limits.max_bytes => {
+                self.allocated = total;
+                Ok(())
+            }
+            _ => Err(DecodeError::BudgetExceeded {
+                max: self.limits.max_bytes,
+            }),
+        }
+    }
+
+    /// Enters a nested value, every successful call has to be paired with `leave`
+    pub fn enter(&mut self) -> Result<(), DecodeError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(DecodeError::TooDeep {
+                max: self.limits.max_depth,
+            });
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::PacketCodec, decoder::Decoder, encoder::Encoder, messages::client::ClientPackets};
+    use tokio_util::{bytes::BytesMut, codec::Decoder as _};
+
+    fn limited(limits: DecodeLimits) -> DecodeContext {
+        DecodeContext::default().with_limits(limits)
+    }
+
+    async fn encoded<T: Encoder + Sync>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        value.encode(&mut bytes).await.unwrap();
+        bytes
+    }
+
+    #[tokio::test]
+    async fn rejects_long_strings() {
+        let bytes = encoded(&"x".repeat(9)).await;
+        let mut ctx = limited(DecodeLimits {
+            max_string_length: 8,
+            ..Default::default()
+        });
+
+        let result = String::decode_with(&mut bytes.as_slice(), &mut ctx).await;
+        assert!(matches!(result, Err(DecodeError::StringTooLong { len: 9, max: 8 })));
+    }
+
+    #[tokio::test]
+    async fn rejects_large_collections() {
+        let bytes = encoded(&vec![0u8; 5]).await;
+        let mut ctx = limited(DecodeLimits {
+            max_elements: 4,
+            ..Default::default()
+        });
+
+        let result = Vec::<u8>::decode_with(&mut bytes.as_slice(), &mut ctx).await;
+        assert!(matches!(result, Err(DecodeError::TooManyElements { len: 5, max: 4 })));
+    }
+
+    #[tokio::test]
+    async fn rejects_deep_nesting() {
+        let bytes = encoded(&vec![vec![vec![0u8]]]).await;
+        let mut ctx = limited(DecodeLimits {
+            max_depth: 2,
+            ..Default::default()
+        });
+        let result = Vec::<Vec<Vec<u8>>>::decode_with(&mut bytes.as_slice(), &mut ctx).await;
+        assert!(matches!(result, Err(DecodeError::TooDeep { max: 2 })));
+
+        // Arrays have no length prefix, but still count towards the depth
+        let mut ctx = limited(DecodeLimits {
+            max_depth: 2,
+            ..Default::default()
+        });
+        let result = <[[[u8; 1]; 1]; 1]>::decode_with(&mut [0u8].as_slice(), &mut ctx).await;
+        assert!(matches!(result, Err(DecodeError::TooDeep { max: 2 })));
+    }
+
+    #[tokio::test]
+    async fn charges_the_budget_across_a_packet() {
+        let bytes = encoded(&(vec![0u8; 6], vec![0u8; 6])).await;
+        let mut ctx = limited(DecodeLimits {
+            max_bytes: 10,
+            ..Default::default()
+        });
+
+        let result = <(Vec<u8>, Vec<u8>)>::decode_with(&mut bytes.as_slice(), &mut ctx).await;
+        assert!(matches!(result, Err(DecodeError::BudgetExceeded { max: 10 })));
+
+        let mut ctx = limited(DecodeLimits {
+            max_bytes: 10,
+            ..Default::default()
+        });
+        let result = <[[u8; 8]; 2]>::decode_with(&mut [0u8; 16].as_slice(), &mut ctx).await;
+        assert!(matches!(result, Err(DecodeError::BudgetExceeded { max: 10 })));
+    }
+
+    #[tokio::test]
+    async fn read_helpers_follow_the_context() {
+        use crate::decoder::DecoderReadExt;
+
+        let bytes = encoded(&"x".repeat(9)).await;
+        let mut ctx = limited(DecodeLimits {
+            max_string_length: 8,
+            ..Default::default()
+        });
+
+        assert!(matches!(
+            bytes.as_slice().read_string(&mut ctx).await,
+            Err(DecodeError::StringTooLong { len: 9, max: 8 })
+        ));
+        assert!(matches!(
+            bytes.as_slice().read_byte_array(&mut ctx).await,
+            Err(DecodeError::StringTooLong { len: 9, max: 8 })
+        ));
+    }
+
+    #[test]
+    fn rejects_long_frames_before_buffering_them() {
+        let limits = DecodeLimits {
+            max_bytes: 16,
+            ..Default::default()
+        };
+        let mut codec = PacketCodec::<ClientPackets, ClientPackets>::new(EncodeContext::default(), limited(limits));
+
+        let mut src = BytesMut::from(&17u32.to_be_bytes()[..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(DecodeError::FrameTooLong { len: 17, max: 16 })
+        ));
+    }
+}
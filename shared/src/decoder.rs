@@ -1,47 +1,124 @@
-use crate::errors::decode::DecodeError;
-use std::{io::Cursor, mem::size_of, ops::Mul};
+use crate::{
+    context::{DecodeContext, Endian},
+    errors::decode::DecodeError,
+    prefix::LengthPrefix,
+    varint::VarIntReadExt,
+};
+#[cfg(feature = "indexmap")]
+use indexmap::{IndexMap, IndexSet};
+use std::{
+    any::type_name,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    hash::{BuildHasher, Hash},
+    io::{self, Cursor},
+    marker::PhantomData,
+    mem::size_of,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64,
+        NonZeroU8, NonZeroUsize,
+    },
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::io::{AsyncRead, AsyncReadExt};
+#[cfg(feature = "uuid")]
 use uuid::Uuid;
 
 pub trait Decoder {
     type Output;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError>;
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError>;
+
+    /// Decodes a value with the default `DecodeContext`
+    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
+        Self::decode_with(reader, &mut DecodeContext::default()).await
+    }
 }
 
 pub trait ReceiveFromStream: Sized {
-    async fn from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Result<Self, DecodeError>;
+    async fn from_bytes_with(buffer: &mut Cursor<Vec<u8>>, ctx: &mut DecodeContext) -> Result<Self, DecodeError>;
+
+    /// Decodes the packet body with the default `DecodeContext`
+    async fn from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Result<Self, DecodeError> {
+        Self::from_bytes_with(buffer, &mut DecodeContext::default()).await
+    }
 }
 
 pub trait DecoderReadExt {
-    async fn read_string(&mut self) -> Result<String, DecodeError>;
-    async fn read_byte_array(&mut self) -> Result<Vec<u8>, DecodeError>;
+    async fn read_length(&mut self, prefix: LengthPrefix, ctx: &DecodeContext) -> Result<usize, DecodeError>;
+    /// Reads the element count of a collection of `T` and charges it against the limits of `ctx`
+    async fn read_collection_length<T>(&mut self, prefix: LengthPrefix, ctx: &mut DecodeContext) -> Result<usize, DecodeError>;
+    /// Reads `len` raw bytes after charging them against the limits of `ctx`
+    async fn read_bytes(&mut self, len: usize, ctx: &mut DecodeContext) -> Result<Vec<u8>, DecodeError>;
+    /// Reads a string with a `u32` length prefix, within the limits of `ctx`
+    async fn read_string(&mut self, ctx: &mut DecodeContext) -> Result<String, DecodeError>;
+    /// Reads a byte array with a `u32` length prefix, within the limits of `ctx`
+    async fn read_byte_array(&mut self, ctx: &mut DecodeContext) -> Result<Vec<u8>, DecodeError>;
 }
 
 impl<R: AsyncRead + Unpin> DecoderReadExt for R {
-    async fn read_byte_array(&mut self) -> Result<Vec<u8>, DecodeError> {
-        let length = self.read_u32().await?;
+    async fn read_length(&mut self, prefix: LengthPrefix, ctx: &DecodeContext) -> Result<usize, DecodeError> {
+        let len = match (prefix, ctx.endian) {
+            (LengthPrefix::U8, _) => u64::from(self.read_u8().await?),
+            (LengthPrefix::U16, Endian::Big) => u64::from(self.read_u16().await?),
+            (LengthPrefix::U16, Endian::Little) => u64::from(self.read_u16_le().await?),
+            (LengthPrefix::U32, Endian::Big) => u64::from(self.read_u32().await?),
+            (LengthPrefix::U32, Endian::Little) => u64::from(self.read_u32_le().await?),
+            (LengthPrefix::VarInt, _) => self.read_varint(u32::BITS).await?,
+        };
+
+        Ok(usize::try_from(len)?)
+    }
 
-        let mut buf = vec![0; length as usize];
-        self.read_exact(&mut buf).await?;
+    async fn read_collection_length<T>(&mut self, prefix: LengthPrefix, ctx: &mut DecodeContext) -> Result<usize, DecodeError> {
+        let len = self.read_length(prefix, ctx).await?;
+        ctx.check_elements(len)?;
+        ctx.allocate(len.saturating_mul(size_of::<T>()))?;
+
+        Ok(len)
+    }
+
+    async fn read_bytes(&mut self, len: usize, ctx: &mut DecodeContext) -> Result<Vec<u8>, DecodeError> {
+        ctx.check_string_length(len)?;
+        ctx.allocate(len)?;
+
+        // Grows with the data that actually arrives instead of trusting `len` up front
+        let mut buf = Vec::new();
+        self.take(len as u64).read_to_end(&mut buf).await?;
+        if buf.len() != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
 
         Ok(buf)
     }
 
-    async fn read_string(&mut self) -> Result<String, DecodeError> {
-        let len = self.read_u32().await? as usize;
+    async fn read_byte_array(&mut self, ctx: &mut DecodeContext) -> Result<Vec<u8>, DecodeError> {
+        let length = self.read_length(LengthPrefix::U32, ctx).await?;
 
-        let mut buf = vec![0; len];
-        self.read_exact(&mut buf).await?;
+        self.read_bytes(length, ctx).await
+    }
 
-        Ok(String::from_utf8(buf)?)
+    async fn read_string(&mut self, ctx: &mut DecodeContext) -> Result<String, DecodeError> {
+        String::decode_prefixed(self, LengthPrefix::U32, ctx).await
     }
 }
 
+/// # Information
+/// Counterpart of `EncodePrefixed`, reads a value whose length prefix has the given width
+pub trait DecodePrefixed: Sized {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError>;
+}
+
 impl Decoder for u8 {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
         Ok(reader.read_u8().await?)
     }
 }
@@ -49,107 +126,656 @@ impl Decoder for u8 {
 impl Decoder for i8 {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
         Ok(reader.read_i8().await?)
     }
 }
 
-impl Decoder for i16 {
+/// Multi-byte numbers follow the byte order of the `DecodeContext`
+macro_rules! impl_number_decoder {
+    ($($ty:ty => $read_be:ident, $read_le:ident);+ $(;)?) => {
+        $(
+            impl Decoder for $ty {
+                type Output = Self;
+
+                async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+                    match ctx.endian {
+                        Endian::Big => Ok(reader.$read_be().await?),
+                        Endian::Little => Ok(reader.$read_le().await?),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_number_decoder!(
+    u16 => read_u16, read_u16_le;
+    i16 => read_i16, read_i16_le;
+    u32 => read_u32, read_u32_le;
+    i32 => read_i32, read_i32_le;
+    u64 => read_u64, read_u64_le;
+    i64 => read_i64, read_i64_le;
+    u128 => read_u128, read_u128_le;
+    i128 => read_i128, read_i128_le;
+    f32 => read_f32, read_f32_le;
+    f64 => read_f64, read_f64_le;
+);
+
+/// Converts a value read at its wire width into `T`, failing with `DecodeError::Conversion` if it doesn't fit
+pub(crate) fn narrow<T: TryFrom<U>, U>(value: U) -> Result<T, DecodeError> {
+    T::try_from(value).map_err(|_| DecodeError::Conversion {
+        from: type_name::<U>(),
+        to: type_name::<T>(),
+    })
+}
+
+impl Decoder for usize {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_i16().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        narrow(u64::decode_with(reader, ctx).await?)
     }
 }
 
-impl Decoder for u16 {
+impl Decoder for isize {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_u16().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        narrow(i64::decode_with(reader, ctx).await?)
     }
 }
 
-impl Decoder for i32 {
+impl Decoder for char {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_i32().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        let val = u32::decode_with(reader, ctx).await?;
+        char::from_u32(val).ok_or(DecodeError::InvalidChar(val))
     }
 }
 
-impl Decoder for u32 {
+impl Decoder for String {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_u32().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl DecodePrefixed for String {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_length(prefix, ctx).await?;
+        let buf = reader.read_bytes(len, ctx).await?;
+
+        Ok(String::from_utf8(buf)?)
     }
 }
 
-impl Decoder for f32 {
+impl Decoder for bool {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_f32().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        match reader.read_u8().await? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::NonBoolValue),
+        }
+    }
+}
+
+impl<T: Decoder<Output = T>> DecodePrefixed for Vec<T> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<T>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut x_vec: Vec<T> = Vec::with_capacity(len);
+        for _ in 0..len {
+            x_vec.push(T::decode_with(reader, ctx).await?);
+        }
+        ctx.leave();
+
+        Ok(x_vec)
+    }
+}
+
+impl<T: Decoder<Output = T>> DecodePrefixed for VecDeque<T> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        Ok(Vec::<T>::decode_prefixed(reader, prefix, ctx).await?.into())
+    }
+}
+
+impl<K, V, S> DecodePrefixed for HashMap<K, V, S>
+where
+    K: Decoder<Output = K> + Eq + Hash,
+    V: Decoder<Output = V>,
+    S: BuildHasher + Default,
+{
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<(K, V)>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut map = HashMap::with_capacity_and_hasher(len, S::default());
+        for _ in 0..len {
+            let key = K::decode_with(reader, ctx).await?;
+            let val = V::decode_with(reader, ctx).await?;
+            if map.insert(key, val).is_some() {
+                return Err(DecodeError::DuplicateKey);
+            }
+        }
+        ctx.leave();
+
+        Ok(map)
+    }
+}
+
+impl<K: Decoder<Output = K> + Ord, V: Decoder<Output = V>> DecodePrefixed for BTreeMap<K, V> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<(K, V)>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let key = K::decode_with(reader, ctx).await?;
+            let val = V::decode_with(reader, ctx).await?;
+            if map.insert(key, val).is_some() {
+                return Err(DecodeError::DuplicateKey);
+            }
+        }
+        ctx.leave();
+
+        Ok(map)
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K, V, S> DecodePrefixed for IndexMap<K, V, S>
+where
+    K: Decoder<Output = K> + Eq + Hash,
+    V: Decoder<Output = V>,
+    S: BuildHasher + Default,
+{
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<(K, V)>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut map = IndexMap::with_capacity_and_hasher(len, S::default());
+        for _ in 0..len {
+            let key = K::decode_with(reader, ctx).await?;
+            let val = V::decode_with(reader, ctx).await?;
+            if map.insert(key, val).is_some() {
+                return Err(DecodeError::DuplicateKey);
+            }
+        }
+        ctx.leave();
+
+        Ok(map)
+    }
+}
+
+impl<T, S> DecodePrefixed for HashSet<T, S>
+where
+    T: Decoder<Output = T> + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<T>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut set = HashSet::with_capacity_and_hasher(len, S::default());
+        for _ in 0..len {
+            if !set.insert(T::decode_with(reader, ctx).await?) {
+                return Err(DecodeError::DuplicateKey);
+            }
+        }
+        ctx.leave();
+
+        Ok(set)
     }
 }
 
-impl Decoder for f64 {
+impl<T: Decoder<Output = T> + Ord> DecodePrefixed for BTreeSet<T> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<T>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut set = BTreeSet::new();
+        for _ in 0..len {
+            if !set.insert(T::decode_with(reader, ctx).await?) {
+                return Err(DecodeError::DuplicateKey);
+            }
+        }
+        ctx.leave();
+
+        Ok(set)
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<T, S> DecodePrefixed for IndexSet<T, S>
+where
+    T: Decoder<Output = T> + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        let len = reader.read_collection_length::<T>(prefix, ctx).await?;
+
+        ctx.enter()?;
+        let mut set = IndexSet::with_capacity_and_hasher(len, S::default());
+        for _ in 0..len {
+            if !set.insert(T::decode_with(reader, ctx).await?) {
+                return Err(DecodeError::DuplicateKey);
+            }
+        }
+        ctx.leave();
+
+        Ok(set)
+    }
+}
+
+impl<T: Decoder<Output = T>> Decoder for Vec<T> {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_f64().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
     }
 }
 
-impl Decoder for u64 {
+impl<T: Decoder<Output = T>> Decoder for VecDeque<T> {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_u64().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
     }
 }
 
-impl Decoder for i64 {
+impl<K, V, S> Decoder for HashMap<K, V, S>
+where
+    K: Decoder<Output = K> + Eq + Hash,
+    V: Decoder<Output = V>,
+    S: BuildHasher + Default,
+{
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        Ok(reader.read_i64().await?)
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
     }
 }
 
-impl Decoder for String {
+impl<K: Decoder<Output = K> + Ord, V: Decoder<Output = V>> Decoder for BTreeMap<K, V> {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        reader.read_string().await
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
     }
 }
 
-impl Decoder for bool {
+#[cfg(feature = "indexmap")]
+impl<K, V, S> Decoder for IndexMap<K, V, S>
+where
+    K: Decoder<Output = K> + Eq + Hash,
+    V: Decoder<Output = V>,
+    S: BuildHasher + Default,
+{
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T, S> Decoder for HashSet<T, S>
+where
+    T: Decoder<Output = T> + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Decoder<Output = T> + Ord> Decoder for BTreeSet<T> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<T, S> Decoder for IndexSet<T, S>
+where
+    T: Decoder<Output = T> + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Decoder<Output = T>, const N: usize> Decoder for [T; N] {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        // The length comes from the type rather than the peer, but nested arrays still add up
+        ctx.allocate(N.saturating_mul(size_of::<T>()))?;
+
+        ctx.enter()?;
+        let mut items = Vec::with_capacity(N);
+        for _ in 0..N {
+            items.push(T::decode_with(reader, ctx).await?);
+        }
+        ctx.leave();
+
+        match items.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("decoded exactly {N} elements"),
+        }
+    }
+}
+
+impl<T: Decoder<Output = T>> Decoder for Option<T> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
         match reader.read_u8().await? {
-            0 => Ok(false),
-            1 => Ok(true),
-            _ => Err(DecodeError::NonBoolValue),
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_with(reader, ctx).await?)),
+            tag => Err(DecodeError::InvalidOptionTag(tag)),
         }
     }
 }
 
-impl<T: Decoder<Output = T>> Decoder for Vec<T> {
+impl<T: DecodePrefixed> DecodePrefixed for Option<T> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        match reader.read_u8().await? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_prefixed(reader, prefix, ctx).await?)),
+            tag => Err(DecodeError::InvalidOptionTag(tag)),
+        }
+    }
+}
+
+impl<T: Decoder<Output = T>, E: Decoder<Output = E>> Decoder for Result<T, E> {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        let len = reader.read_u32().await?;
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        match reader.read_u8().await? {
+            0 => Ok(Ok(T::decode_with(reader, ctx).await?)),
+            1 => Ok(Err(E::decode_with(reader, ctx).await?)),
+            tag => Err(DecodeError::InvalidResultTag(tag)),
+        }
+    }
+}
 
-        let mut x_vec: Vec<T> = Vec::with_capacity((len as usize).mul(size_of::<T>()));
-        for _ in 0..len {
-            x_vec.push(T::decode(reader).await?);
+macro_rules! impl_non_zero_decoder {
+    ($($name:ty => $inner:ty),+) => {
+        $(
+            impl Decoder for $name {
+                type Output = Self;
+
+                async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+                    <$name>::new(<$inner>::decode_with(reader, ctx).await?).ok_or(DecodeError::ZeroValue)
+                }
+            }
+        )+
+    };
+}
+
+impl_non_zero_decoder!(
+    NonZeroU8 => u8,
+    NonZeroU16 => u16,
+    NonZeroU32 => u32,
+    NonZeroU64 => u64,
+    NonZeroU128 => u128,
+    NonZeroUsize => usize,
+    NonZeroI8 => i8,
+    NonZeroI16 => i16,
+    NonZeroI32 => i32,
+    NonZeroI64 => i64,
+    NonZeroI128 => i128,
+    NonZeroIsize => isize
+);
+
+impl<T: Decoder<Output = T>> Decoder for Box<T> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        ctx.allocate(size_of::<T>())?;
+        ctx.enter()?;
+        let val = T::decode_with(reader, ctx).await?;
+        ctx.leave();
+
+        Ok(Box::new(val))
+    }
+}
+
+impl<T: Decoder<Output = T>> Decoder for Arc<T> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        ctx.allocate(size_of::<T>())?;
+        ctx.enter()?;
+        let val = T::decode_with(reader, ctx).await?;
+        ctx.leave();
+
+        Ok(Arc::new(val))
+    }
+}
+
+impl Decoder for Cow<'_, str> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Self::decode_prefixed(reader, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Decoder<Output = T> + Clone> Decoder for Cow<'_, [T]> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(Cow::Owned(Vec::<T>::decode_with(reader, ctx).await?))
+    }
+}
+
+impl DecodePrefixed for Cow<'_, str> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        Ok(Cow::Owned(String::decode_prefixed(reader, prefix, ctx).await?))
+    }
+}
+
+impl<T: Decoder<Output = T> + Clone> DecodePrefixed for Cow<'_, [T]> {
+    async fn decode_prefixed<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        prefix: LengthPrefix,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        Ok(Cow::Owned(Vec::<T>::decode_prefixed(reader, prefix, ctx).await?))
+    }
+}
+
+impl Decoder for () {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(_reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(())
+    }
+}
+
+impl<T: ?Sized> Decoder for PhantomData<T> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(_reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(PhantomData)
+    }
+}
+
+macro_rules! impl_tuple_decoder {
+    ($($name:ident)+) => {
+        impl<$($name: Decoder<Output = $name>),+> Decoder for ($($name,)+) {
+            type Output = Self;
+
+            async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+                Ok(($($name::decode_with(reader, ctx).await?,)+))
+            }
         }
+    };
+}
 
-        Ok(x_vec)
+impl_tuple_decoder!(A);
+impl_tuple_decoder!(A B);
+impl_tuple_decoder!(A B C);
+impl_tuple_decoder!(A B C D);
+impl_tuple_decoder!(A B C D E);
+impl_tuple_decoder!(A B C D E F);
+impl_tuple_decoder!(A B C D E F G);
+impl_tuple_decoder!(A B C D E F G H);
+impl_tuple_decoder!(A B C D E F G H I);
+impl_tuple_decoder!(A B C D E F G H I J);
+impl_tuple_decoder!(A B C D E F G H I J K);
+impl_tuple_decoder!(A B C D E F G H I J K L);
+
+impl Decoder for Duration {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        let secs = u64::decode_with(reader, ctx).await?;
+        let nanos = u32::decode_with(reader, ctx).await?;
+        if nanos >= 1_000_000_000 {
+            return Err(DecodeError::InvalidNanos(nanos));
+        }
+
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl Decoder for SystemTime {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        let secs = i64::decode_with(reader, ctx).await?;
+        let nanos = u32::decode_with(reader, ctx).await?;
+        if nanos >= 1_000_000_000 {
+            return Err(DecodeError::InvalidNanos(nanos));
+        }
+
+        let secs = u64::try_from(secs).map_err(|_| DecodeError::BeforeUnixEpoch)?;
+        UNIX_EPOCH
+            .checked_add(Duration::new(secs, nanos))
+            .ok_or(DecodeError::TimeOutOfRange)
+    }
+}
+
+impl Decoder for Ipv4Addr {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(Ipv4Addr::from(<[u8; 4]>::decode_with(reader, ctx).await?))
+    }
+}
+
+impl Decoder for Ipv6Addr {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(Ipv6Addr::from(<[u8; 16]>::decode_with(reader, ctx).await?))
+    }
+}
+
+impl Decoder for IpAddr {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        match reader.read_u8().await? {
+            4 => Ok(IpAddr::V4(Ipv4Addr::decode_with(reader, ctx).await?)),
+            6 => Ok(IpAddr::V6(Ipv6Addr::decode_with(reader, ctx).await?)),
+            tag => Err(DecodeError::InvalidIpVersion(tag)),
+        }
+    }
+}
+
+impl Decoder for SocketAddrV4 {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        let ip = Ipv4Addr::decode_with(reader, ctx).await?;
+        let port = u16::decode_with(reader, ctx).await?;
+
+        Ok(SocketAddrV4::new(ip, port))
+    }
+}
+
+impl Decoder for SocketAddrV6 {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        let ip = Ipv6Addr::decode_with(reader, ctx).await?;
+        let port = u16::decode_with(reader, ctx).await?;
+        let flowinfo = u32::decode_with(reader, ctx).await?;
+        let scope_id = u32::decode_with(reader, ctx).await?;
+
+        Ok(SocketAddrV6::new(ip, port, flowinfo, scope_id))
+    }
+}
+
+impl Decoder for SocketAddr {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        match reader.read_u8().await? {
+            4 => Ok(SocketAddr::V4(SocketAddrV4::decode_with(reader, ctx).await?)),
+            6 => Ok(SocketAddr::V6(SocketAddrV6::decode_with(reader, ctx).await?)),
+            tag => Err(DecodeError::InvalidIpVersion(tag)),
+        }
     }
 }
 
@@ -157,8 +783,159 @@ impl<T: Decoder<Output = T>> Decoder for Vec<T> {
 impl Decoder for Uuid {
     type Output = Self;
 
-    async fn decode<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self::Output, DecodeError> {
-        let val = reader.read_u128().await?;
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        let val = u128::decode_with(reader, ctx).await?;
         Ok(Uuid::from_u128(val))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Encoder;
+    use std::fmt::Debug;
+
+    async fn encoded<T: Encoder + Sync>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        value.encode(&mut bytes).await.unwrap();
+        bytes
+    }
+
+    async fn decoded<T: Decoder<Output = T>>(bytes: &[u8]) -> Result<T, DecodeError> {
+        T::decode(&mut &bytes[..]).await
+    }
+
+    async fn round_trip<T: Encoder + Decoder<Output = T> + Sync + PartialEq + Debug>(value: T) {
+        assert_eq!(decoded::<T>(&encoded(&value).await).await.unwrap(), value);
+    }
+
+    #[tokio::test]
+    async fn round_trips_collections() {
+        round_trip(HashMap::from([(1u8, "one".to_string()), (2, "two".to_string())])).await;
+        round_trip(BTreeMap::from([(1u16, vec![1u8]), (2, vec![])])).await;
+        round_trip(HashSet::from([1u32, 2, 3])).await;
+        round_trip(BTreeSet::from(['a', 'b'])).await;
+        round_trip(VecDeque::from([1i64, -1])).await;
+        round_trip(BTreeMap::<u8, u8>::new()).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_keys() {
+        // Maps and sets share the wire format of a `Vec` of their entries
+        let entries = encoded(&vec![(1u8, 2u8), (1, 3)]).await;
+        assert!(matches!(decoded::<HashMap<u8, u8>>(&entries).await, Err(DecodeError::DuplicateKey)));
+        assert!(matches!(
+            decoded::<BTreeMap<u8, u8>>(&entries).await,
+            Err(DecodeError::DuplicateKey)
+        ));
+
+        let elements = encoded(&vec![1u8, 1]).await;
+        assert!(matches!(decoded::<HashSet<u8>>(&elements).await, Err(DecodeError::DuplicateKey)));
+        assert!(matches!(decoded::<BTreeSet<u8>>(&elements).await, Err(DecodeError::DuplicateKey)));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_chars() {
+        round_trip('\u{10FFFF}').await;
+
+        for invalid in [0xD800u32, 0x11_0000] {
+            let result = decoded::<char>(&encoded(&invalid).await).await;
+            assert!(matches!(result, Err(DecodeError::InvalidChar(val)) if val == invalid));
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_zero_for_non_zero_types() {
+        round_trip(NonZeroU32::MIN).await;
+        round_trip(NonZeroI8::new(-1).unwrap()).await;
+
+        assert!(matches!(
+            decoded::<NonZeroU32>(&encoded(&0u32).await).await,
+            Err(DecodeError::ZeroValue)
+        ));
+        assert!(matches!(
+            decoded::<NonZeroI64>(&encoded(&0i64).await).await,
+            Err(DecodeError::ZeroValue)
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_sizes_as_64_bits() {
+        round_trip(usize::MAX).await;
+        round_trip(isize::MIN).await;
+        assert_eq!(encoded(&1usize).await, encoded(&1u64).await);
+    }
+
+    #[test]
+    fn reports_values_that_dont_fit() {
+        assert!(matches!(
+            narrow::<u32, u64>(u64::MAX),
+            Err(DecodeError::Conversion { from: "u64", to: "u32" })
+        ));
+        assert!(matches!(
+            narrow::<i8, i64>(-129),
+            Err(DecodeError::Conversion { from: "i64", to: "i8" })
+        ));
+        assert_eq!(narrow::<u32, u64>(7).unwrap(), 7);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[tokio::test]
+    async fn rejects_sizes_beyond_the_platform() {
+        let result = decoded::<usize>(&encoded(&u64::MAX).await).await;
+        assert!(matches!(result, Err(DecodeError::Conversion { from: "u64", to: "usize" })));
+    }
+
+    #[tokio::test]
+    async fn round_trips_times() {
+        round_trip(Duration::ZERO).await;
+        round_trip(Duration::new(u64::MAX, 999_999_999)).await;
+        round_trip(UNIX_EPOCH).await;
+        round_trip(UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789)).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_nanoseconds() {
+        let duration = encoded(&(1u64, 1_000_000_000u32)).await;
+        assert!(matches!(
+            decoded::<Duration>(&duration).await,
+            Err(DecodeError::InvalidNanos(1_000_000_000))
+        ));
+
+        let time = encoded(&(1i64, u32::MAX)).await;
+        assert!(matches!(
+            decoded::<SystemTime>(&time).await,
+            Err(DecodeError::InvalidNanos(u32::MAX))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_times_before_the_epoch() {
+        // Half a second before the epoch is floored to -1 seconds and 500 000 000 nanoseconds
+        let before = encoded(&(UNIX_EPOCH - Duration::from_millis(500))).await;
+        assert_eq!(before, encoded(&(-1i64, 500_000_000u32)).await);
+        assert!(matches!(decoded::<SystemTime>(&before).await, Err(DecodeError::BeforeUnixEpoch)));
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[tokio::test]
+    async fn round_trips_index_collections_in_order() {
+        let map = IndexMap::<u8, u8>::from_iter([(3, 1), (1, 2), (2, 3)]);
+        let decoded_map = decoded::<IndexMap<u8, u8>>(&encoded(&map).await).await.unwrap();
+        assert!(decoded_map.keys().eq(map.keys()));
+
+        let set = IndexSet::<u8>::from_iter([3, 1, 2]);
+        let decoded_set = decoded::<IndexSet<u8>>(&encoded(&set).await).await.unwrap();
+        assert!(decoded_set.iter().eq(set.iter()));
+
+        let entries = encoded(&vec![(1u8, 2u8), (1, 3)]).await;
+        assert!(matches!(
+            decoded::<IndexMap<u8, u8>>(&entries).await,
+            Err(DecodeError::DuplicateKey)
+        ));
+        assert!(matches!(
+            decoded::<IndexSet<u8>>(&encoded(&vec![1u8, 1]).await).await,
+            Err(DecodeError::DuplicateKey)
+        ));
+    }
+}
@@ -1,101 +1,198 @@
-use crate::errors::encode::EncodeError;
-use std::future::Future;
+use crate::{
+    context::{EncodeContext, Endian},
+    errors::encode::EncodeError,
+    prefix::LengthPrefix,
+    varint::VarIntWriteExt,
+};
+#[cfg(feature = "indexmap")]
+use indexmap::{IndexMap, IndexSet};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    future::{ready, Future},
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64,
+        NonZeroU8, NonZeroUsize,
+    },
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::io::{AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "uuid")]
 use uuid::Uuid;
 
 pub trait Encoder {
-    fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> impl Future<Output = Result<(), EncodeError>> + Send;
+    fn encode_with<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        ctx: &EncodeContext,
+    ) -> impl Future<Output = Result<(), EncodeError>> + Send;
+
+    /// Encodes the value with the default `EncodeContext`
+    fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> impl Future<Output = Result<(), EncodeError>> + Send
+    where
+        Self: Sync,
+    {
+        async move { self.encode_with(writer, &EncodeContext::default()).await }
+    }
 }
 
 pub trait EncoderWriteExt {
-    async fn write_string(&mut self, value: &str) -> Result<(), EncodeError>;
+    async fn write_length(&mut self, len: usize, prefix: LengthPrefix, ctx: &EncodeContext) -> Result<(), EncodeError>;
+    /// Writes a string with a `u32` length prefix in the byte order of `ctx`, the counterpart of `read_string`
+    async fn write_string(&mut self, value: &str, ctx: &EncodeContext) -> Result<(), EncodeError>;
 }
 
 impl<W: AsyncWrite + Unpin> EncoderWriteExt for W {
-    async fn write_string(&mut self, value: &str) -> Result<(), EncodeError> {
-        let len = value.len();
+    async fn write_length(&mut self, len: usize, prefix: LengthPrefix, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        if len > prefix.max() {
+            return Err(EncodeError::LengthOverflow { len, prefix });
+        }
+
+        match (prefix, ctx.endian) {
+            (LengthPrefix::U8, _) => self.write_u8(len as u8).await?,
+            (LengthPrefix::U16, Endian::Big) => self.write_u16(len as u16).await?,
+            (LengthPrefix::U16, Endian::Little) => self.write_u16_le(len as u16).await?,
+            (LengthPrefix::U32, Endian::Big) => self.write_u32(len as u32).await?,
+            (LengthPrefix::U32, Endian::Little) => self.write_u32_le(len as u32).await?,
+            (LengthPrefix::VarInt, _) => self.write_varint(len as u64).await?,
+        }
 
-        self.write_u32(len.try_into()?).await?;
+        Ok(())
+    }
+
+    async fn write_string(&mut self, value: &str, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.write_length(value.len(), LengthPrefix::U32, ctx).await?;
         self.write_all(value.as_bytes()).await?;
 
         Ok(())
     }
 }
 
+/// # Information
+/// Implemented by every type that is written with a length prefix, so the width of that prefix can be chosen per field.
+/// The plain `Encoder` impls of these types use `LengthPrefix::U32`.
+pub trait EncodePrefixed {
+    fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> impl Future<Output = Result<(), EncodeError>> + Send;
+}
+
 impl Encoder for u8 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
         Ok(writer.write_u8(*self).await?)
     }
 }
 
 impl Encoder for i8 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
         Ok(writer.write_i8(*self).await?)
     }
 }
 
-impl Encoder for u16 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_u16(*self).await?)
-    }
+/// Multi-byte numbers follow the byte order of the `EncodeContext`
+macro_rules! impl_number_encoder {
+    ($($ty:ty => $write_be:ident, $write_le:ident);+ $(;)?) => {
+        $(
+            impl Encoder for $ty {
+                async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+                    match ctx.endian {
+                        Endian::Big => Ok(writer.$write_be(*self).await?),
+                        Endian::Little => Ok(writer.$write_le(*self).await?),
+                    }
+                }
+            }
+        )+
+    };
 }
 
-impl Encoder for i16 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_i16(*self).await?)
-    }
-}
+impl_number_encoder!(
+    u16 => write_u16, write_u16_le;
+    i16 => write_i16, write_i16_le;
+    u32 => write_u32, write_u32_le;
+    i32 => write_i32, write_i32_le;
+    u64 => write_u64, write_u64_le;
+    i64 => write_i64, write_i64_le;
+    u128 => write_u128, write_u128_le;
+    i128 => write_i128, write_i128_le;
+    f32 => write_f32, write_f32_le;
+    f64 => write_f64, write_f64_le;
+);
 
-impl Encoder for u32 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_u32(*self).await?)
+/// Always written as a `u64` so both sides agree regardless of pointer width
+impl Encoder for usize {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        u64::try_from(*self)?.encode_with(writer, ctx).await
     }
 }
 
-impl Encoder for i32 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_i32(*self).await?)
+/// Always written as an `i64` so both sides agree regardless of pointer width
+impl Encoder for isize {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        i64::try_from(*self)?.encode_with(writer, ctx).await
     }
 }
 
-impl Encoder for u64 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_u64(*self).await?)
+/// Written as the `u32` value of the Unicode scalar
+impl Encoder for char {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        u32::from(*self).encode_with(writer, ctx).await
     }
 }
 
-impl Encoder for i64 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_i64(*self).await?)
+impl Encoder for String {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
     }
 }
 
-impl Encoder for f32 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_f32(*self).await?)
+impl Encoder for &str {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
     }
 }
 
-impl Encoder for f64 {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_f64(*self).await?)
+impl EncodePrefixed for str {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+        Ok(writer.write_all(self.as_bytes()).await?)
     }
 }
 
-impl Encoder for String {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        writer.write_string(self).await
+impl EncodePrefixed for &str {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        (**self).encode_prefixed(writer, prefix, ctx).await
     }
 }
 
-impl Encoder for &str {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        writer.write_string(self).await
+impl EncodePrefixed for String {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        self.as_str().encode_prefixed(writer, prefix, ctx).await
     }
 }
 
 impl Encoder for bool {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
         let val = match *self {
             true => 1,
             false => 0,
@@ -105,52 +202,537 @@ impl Encoder for bool {
     }
 }
 
-impl Encoder for Vec<u8> {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        let len = self.len();
+/// Written as a length prefix followed by each element
+impl<T: Encoder + Sync> EncodePrefixed for [T] {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for val in self {
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Encoder + Sync> EncodePrefixed for &[T] {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        (**self).encode_prefixed(writer, prefix, ctx).await
+    }
+}
 
-        writer.write_u32(len.try_into()?).await?;
-        Ok(writer.write_all(&self[..]).await?)
+impl<T: Encoder + Sync> EncodePrefixed for Vec<T> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        self.as_slice().encode_prefixed(writer, prefix, ctx).await
     }
 }
 
-impl Encoder for Vec<String> {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        writer.write_u32(self.len().try_into()?).await?;
+impl<T: Encoder + Sync> EncodePrefixed for VecDeque<T> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
 
         for val in self {
-            writer.write_string(val).await?;
+            val.encode_with(writer, ctx).await?;
         }
 
         Ok(())
     }
 }
 
-impl<const N: usize> Encoder for [u8; N]
-where
-    [u8; N]: Sized,
-{
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        writer.write_u32(self.len().try_into()?).await?;
-        writer.write_all(self).await?;
+impl<T: Encoder + Sync> Encoder for [T] {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Encoder + Sync> Encoder for &[T] {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Encoder + Sync> Encoder for Vec<T> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Encoder + Sync> Encoder for VecDeque<T> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+/// The length is part of the type, so only the elements are written
+impl<T: Encoder + Sync, const N: usize> Encoder for [T; N] {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        for val in self {
+            val.encode_with(writer, ctx).await?;
+        }
 
         Ok(())
     }
 }
 
+/// Written as a length prefix followed by each key and its value
+impl<K: Encoder + Sync, V: Encoder + Sync, S: Sync> EncodePrefixed for HashMap<K, V, S> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for (key, val) in self {
+            key.encode_with(writer, ctx).await?;
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: Encoder + Sync, V: Encoder + Sync> EncodePrefixed for BTreeMap<K, V> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for (key, val) in self {
+            key.encode_with(writer, ctx).await?;
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K: Encoder + Sync, V: Encoder + Sync, S: Sync> EncodePrefixed for IndexMap<K, V, S> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for (key, val) in self {
+            key.encode_with(writer, ctx).await?;
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Written as a length prefix followed by each element
+impl<T: Encoder + Sync, S: Sync> EncodePrefixed for HashSet<T, S> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for val in self {
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Encoder + Sync> EncodePrefixed for BTreeSet<T> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for val in self {
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<T: Encoder + Sync, S: Sync> EncodePrefixed for IndexSet<T, S> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        writer.write_length(self.len(), prefix, ctx).await?;
+
+        for val in self {
+            val.encode_with(writer, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: Encoder + Sync, V: Encoder + Sync, S: Sync> Encoder for HashMap<K, V, S> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<K: Encoder + Sync, V: Encoder + Sync> Encoder for BTreeMap<K, V> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K: Encoder + Sync, V: Encoder + Sync, S: Sync> Encoder for IndexMap<K, V, S> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Encoder + Sync, S: Sync> Encoder for HashSet<T, S> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Encoder + Sync> Encoder for BTreeSet<T> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<T: Encoder + Sync, S: Sync> Encoder for IndexSet<T, S> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+/// Written as a `u8` presence tag (`0` = `None`, `1` = `Some`) followed by the value if present
 impl<T: Encoder + Sync> Encoder for Option<T> {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        if let Some(val) = self {
-            val.encode(writer).await
-        } else {
-            0u8.encode(writer).await
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        match self {
+            Some(val) => {
+                writer.write_u8(1).await?;
+                val.encode_with(writer, ctx).await
+            }
+            None => Ok(writer.write_u8(0).await?),
+        }
+    }
+}
+
+/// Applies the length prefix to the contained value, so `#[len(..)]` also works on optional fields
+impl<T: EncodePrefixed + Sync> EncodePrefixed for Option<T> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        match self {
+            Some(val) => {
+                writer.write_u8(1).await?;
+                val.encode_prefixed(writer, prefix, ctx).await
+            }
+            None => Ok(writer.write_u8(0).await?),
+        }
+    }
+}
+
+/// Written as a `u8` tag (`0` = `Ok`, `1` = `Err`) followed by the contained value
+impl<T: Encoder + Sync, E: Encoder + Sync> Encoder for Result<T, E> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        match self {
+            Ok(val) => {
+                writer.write_u8(0).await?;
+                val.encode_with(writer, ctx).await
+            }
+            Err(err) => {
+                writer.write_u8(1).await?;
+                err.encode_with(writer, ctx).await
+            }
+        }
+    }
+}
+
+macro_rules! impl_non_zero_encoder {
+    ($($name:ty),+) => {
+        $(
+            impl Encoder for $name {
+                async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+                    self.get().encode_with(writer, ctx).await
+                }
+            }
+        )+
+    };
+}
+
+impl_non_zero_encoder!(
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroU128,
+    NonZeroUsize,
+    NonZeroI8,
+    NonZeroI16,
+    NonZeroI32,
+    NonZeroI64,
+    NonZeroI128,
+    NonZeroIsize
+);
+
+impl<T: Encoder + ?Sized> Encoder for Box<T> {
+    fn encode_with<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        ctx: &EncodeContext,
+    ) -> impl Future<Output = Result<(), EncodeError>> + Send {
+        (**self).encode_with(writer, ctx)
+    }
+}
+
+impl<T: Encoder + ?Sized> Encoder for Arc<T> {
+    fn encode_with<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        ctx: &EncodeContext,
+    ) -> impl Future<Output = Result<(), EncodeError>> + Send {
+        (**self).encode_with(writer, ctx)
+    }
+}
+
+// There is deliberately no impl for `Rc`. `Encoder` futures are `Send`, and a struct holding an `Rc` isn't `Sync`,
+// so the future of a derived `Serialize` could never borrow it. Shared fields use `Arc` instead.
+
+impl Encoder for Cow<'_, str> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.encode_prefixed(writer, LengthPrefix::U32, ctx).await
+    }
+}
+
+impl<T: Encoder + Clone + Sync> Encoder for Cow<'_, [T]> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        (**self).encode_with(writer, ctx).await
+    }
+}
+
+impl EncodePrefixed for Cow<'_, str> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        (**self).encode_prefixed(writer, prefix, ctx).await
+    }
+}
+
+impl<T: Encoder + Clone + Sync> EncodePrefixed for Cow<'_, [T]> {
+    async fn encode_prefixed<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+        prefix: LengthPrefix,
+        ctx: &EncodeContext,
+    ) -> Result<(), EncodeError> {
+        (**self).encode_prefixed(writer, prefix, ctx).await
+    }
+}
+
+impl Encoder for () {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, _writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl<T: ?Sized> Encoder for PhantomData<T> {
+    fn encode_with<W: AsyncWrite + Unpin + Send>(
+        &self,
+        _writer: &mut W,
+        _ctx: &EncodeContext,
+    ) -> impl Future<Output = Result<(), EncodeError>> + Send {
+        ready(Ok(()))
+    }
+}
+
+/// Tuples are written element by element without any framing
+macro_rules! impl_tuple_encoder {
+    ($($name:ident)+) => {
+        impl<$($name: Encoder + Sync),+> Encoder for ($($name,)+) {
+            #[allow(non_snake_case)]
+            async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+                let ($($name,)+) = self;
+                $($name.encode_with(writer, ctx).await?;)+
+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_tuple_encoder!(A);
+impl_tuple_encoder!(A B);
+impl_tuple_encoder!(A B C);
+impl_tuple_encoder!(A B C D);
+impl_tuple_encoder!(A B C D E);
+impl_tuple_encoder!(A B C D E F);
+impl_tuple_encoder!(A B C D E F G);
+impl_tuple_encoder!(A B C D E F G H);
+impl_tuple_encoder!(A B C D E F G H I);
+impl_tuple_encoder!(A B C D E F G H I J);
+impl_tuple_encoder!(A B C D E F G H I J K);
+impl_tuple_encoder!(A B C D E F G H I J K L);
+
+/// Written as `u64` seconds followed by `u32` nanoseconds
+impl Encoder for Duration {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.as_secs().encode_with(writer, ctx).await?;
+        self.subsec_nanos().encode_with(writer, ctx).await
+    }
+}
+
+/// Written as signed `i64` seconds relative to the Unix epoch followed by `u32` nanoseconds.
+/// Times before the epoch are floored to a negative second count so the receiver can reject them.
+impl Encoder for SystemTime {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        let (secs, nanos) = match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => (i64::try_from(since.as_secs())?, since.subsec_nanos()),
+            Err(err) => {
+                let before = err.duration();
+                let secs = -i64::try_from(before.as_secs())?;
+                match before.subsec_nanos() {
+                    0 => (secs, 0),
+                    nanos => (secs - 1, 1_000_000_000 - nanos),
+                }
+            }
+        };
+
+        secs.encode_with(writer, ctx).await?;
+        nanos.encode_with(writer, ctx).await
+    }
+}
+
+impl Encoder for Ipv4Addr {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
+        Ok(writer.write_all(&self.octets()).await?)
+    }
+}
+
+impl Encoder for Ipv6Addr {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
+        Ok(writer.write_all(&self.octets()).await?)
+    }
+}
+
+/// Written as a `u8` version tag (`4` or `6`) followed by the address octets
+impl Encoder for IpAddr {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        match self {
+            IpAddr::V4(addr) => {
+                writer.write_u8(4).await?;
+                addr.encode_with(writer, ctx).await
+            }
+            IpAddr::V6(addr) => {
+                writer.write_u8(6).await?;
+                addr.encode_with(writer, ctx).await
+            }
+        }
+    }
+}
+
+impl Encoder for SocketAddrV4 {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.ip().encode_with(writer, ctx).await?;
+        self.port().encode_with(writer, ctx).await
+    }
+}
+
+impl Encoder for SocketAddrV6 {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.ip().encode_with(writer, ctx).await?;
+        self.port().encode_with(writer, ctx).await?;
+        self.flowinfo().encode_with(writer, ctx).await?;
+        self.scope_id().encode_with(writer, ctx).await
+    }
+}
+
+/// Written as a `u8` version tag (`4` or `6`) followed by the address
+impl Encoder for SocketAddr {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        match self {
+            SocketAddr::V4(addr) => {
+                writer.write_u8(4).await?;
+                addr.encode_with(writer, ctx).await
+            }
+            SocketAddr::V6(addr) => {
+                writer.write_u8(6).await?;
+                addr.encode_with(writer, ctx).await
+            }
         }
     }
 }
 
 #[cfg(feature = "uuid")]
 impl Encoder for Uuid {
-    async fn encode<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W) -> Result<(), EncodeError> {
-        Ok(writer.write_u128(self.as_u128()).await?)
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        self.as_u128().encode_with(writer, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::DecodeContext,
+        decoder::{Decoder, DecoderReadExt},
+    };
+
+    #[tokio::test]
+    async fn write_string_follows_the_byte_order() {
+        for endian in [Endian::Big, Endian::Little] {
+            let mut bytes = vec![];
+            bytes.write_string("hwid", &EncodeContext::new(endian)).await.unwrap();
+
+            let mut encoded = vec![];
+            "hwid".encode_with(&mut encoded, &EncodeContext::new(endian)).await.unwrap();
+            assert_eq!(bytes, encoded);
+
+            let mut ctx = DecodeContext::new(endian);
+            assert_eq!(bytes.as_slice().read_string(&mut ctx).await.unwrap(), "hwid");
+            assert_eq!(String::decode_with(&mut bytes.as_slice(), &mut ctx).await.unwrap(), "hwid");
+        }
     }
 }
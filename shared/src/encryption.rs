@@ -0,0 +1,317 @@
+use crate::errors::{decode::DecodeError, encode::EncodeError, handshake::HandshakeError};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{self, Debug, Formatter},
+    mem::size_of,
+};
+use x25519_dalek::ReusableSecret;
+pub use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+/// Binds the derived keys to this protocol, so they can't collide with keys of another use of the same secrets
+const KEY_INFO: &[u8] = b"NPRT session keys";
+
+/// Which end of the connection derives the keys, what one side seals with the other opens with
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Client,
+    Server,
+}
+
+/// The ephemeral key pair of one side of a handshake
+pub(crate) struct KeyShare {
+    secret: ReusableSecret,
+    pub public: PublicKey,
+}
+
+impl KeyShare {
+    pub fn random() -> Self {
+        let secret = ReusableSecret::random();
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// The session keys of the client, `server_key` is the pinned static key of the server.
+    /// Mixing it into the keys means only the holder of its secret can read or write the session.
+    pub fn client_session(
+        &self,
+        server_share: &PublicKey,
+        server_key: &PublicKey,
+        transcript: &[u8],
+    ) -> Result<SessionKeys, HandshakeError> {
+        let ephemeral = self.secret.diffie_hellman(server_share);
+        let identity = self.secret.diffie_hellman(server_key);
+        if !ephemeral.was_contributory() || !identity.was_contributory() {
+            return Err(HandshakeError::WeakKeyShare);
+        }
+
+        Ok(SessionKeys::derive(
+            Role::Client,
+            ephemeral.as_bytes(),
+            identity.as_bytes(),
+            transcript,
+        ))
+    }
+
+    /// The session keys of the server, the counterpart of `client_session`
+    pub fn server_session(
+        &self,
+        client_share: &PublicKey,
+        identity: &StaticSecret,
+        transcript: &[u8],
+    ) -> Result<SessionKeys, HandshakeError> {
+        let ephemeral = self.secret.diffie_hellman(client_share);
+        let identity = identity.diffie_hellman(client_share);
+        if !ephemeral.was_contributory() || !identity.was_contributory() {
+            return Err(HandshakeError::WeakKeyShare);
+        }
+
+        Ok(SessionKeys::derive(
+            Role::Server,
+            ephemeral.as_bytes(),
+            identity.as_bytes(),
+            transcript,
+        ))
+    }
+}
+
+/// One direction of a session, the key and the counter of the next frame
+struct Direction {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Direction {
+    fn new(key: &[u8]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            counter: 0,
+        }
+    }
+}
+
+/// The counter is the nonce, so each direction never seals two frames with the same one
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[size_of::<u32>()..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+/// # Information
+/// The keys of an encrypted session, derived during the handshake from both ephemeral key shares and the static server key.
+/// Each direction has its own key and frame counter. A sealed frame starts with its counter,
+/// so the receiver can tell a replayed or dropped frame from a tampered one.
+pub struct SessionKeys {
+    send: Direction,
+    receive: Direction,
+}
+
+impl SessionKeys {
+    fn derive(role: Role, ephemeral: &[u8], identity: &[u8], transcript: &[u8]) -> Self {
+        let mut secret = Zeroizing::new([0u8; 64]);
+        secret[..32].copy_from_slice(ephemeral);
+        secret[32..].copy_from_slice(identity);
+
+        // The transcript covers both hellos, so tampering with the negotiation leaves both sides with different keys
+        let salt = Sha256::digest(transcript);
+        let mut keys = Zeroizing::new([0u8; 64]);
+        Hkdf::<Sha256>::new(Some(&salt), secret.as_slice())
+            .expand(KEY_INFO, keys.as_mut_slice())
+            .expect("64 bytes is a valid HKDF-SHA256 output length");
+
+        let (client, server) = keys.split_at(32);
+        let (send, receive) = match role {
+            Role::Client => (client, server),
+            Role::Server => (server, client),
+        };
+
+        Self {
+            send: Direction::new(send),
+            receive: Direction::new(receive),
+        }
+    }
+
+    /// Seals the hash of the handshake transcript, the first frame each side sends to prove it derived the same keys
+    pub(crate) fn confirmation(&mut self, transcript: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        self.seal(&Sha256::digest(transcript))
+    }
+
+    /// Opens the `confirmation` of the peer, which only succeeds if both sides derived the same keys from the same transcript
+    pub(crate) fn confirm(&mut self, sealed: &[u8], transcript: &[u8]) -> Result<(), HandshakeError> {
+        match self.open(sealed) {
+            Ok(hash) if hash[..] == Sha256::digest(transcript)[..] => Ok(()),
+            _ => Err(HandshakeError::KeyConfirmationFailed),
+        }
+    }
+
+    /// Encrypts and authenticates the next outgoing frame
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        let counter = self.send.counter;
+        self.send.counter = counter.checked_add(1).ok_or(EncodeError::NonceExhausted)?;
+
+        let aad = counter.to_be_bytes();
+        let ciphertext = self
+            .send
+            .cipher
+            .encrypt(&nonce(counter), Payload { msg: plaintext, aad: &aad })
+            .expect("frames are far below the length limit of ChaCha20-Poly1305");
+
+        let mut sealed = Vec::with_capacity(aad.len() + ciphertext.len());
+        sealed.extend_from_slice(&aad);
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts the next incoming frame, which has to carry exactly the expected counter
+    pub fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let (counter, ciphertext) = sealed
+            .split_first_chunk::<{ size_of::<u64>() }>()
+            .ok_or(DecodeError::FrameTampered)?;
+        let counter = u64::from_be_bytes(*counter);
+        let expected = self.receive.counter;
+
+        if counter < expected {
+            return Err(DecodeError::FrameReplayed { counter, expected });
+        }
+        if counter > expected {
+            return Err(DecodeError::FrameOutOfOrder { counter, expected });
+        }
+
+        let aad = counter.to_be_bytes();
+        let plaintext = self
+            .receive
+            .cipher
+            .decrypt(
+                &nonce(counter),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| DecodeError::FrameTampered)?;
+
+        self.receive.counter += 1;
+        Ok(plaintext)
+    }
+}
+
+impl Debug for SessionKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionKeys")
+            .field("sent", &self.send.counter)
+            .field("received", &self.receive.counter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        capabilities::Capabilities,
+        handshake::{self, Hello, Negotiated, RejectReason, PROTOCOL_VERSIONS},
+    };
+
+    type Outcome = Result<Negotiated, HandshakeError>;
+
+    /// Runs both sides of the handshake over a loopback stream
+    async fn handshake(identity: Option<StaticSecret>, server_key: Option<PublicKey>) -> (Outcome, Outcome) {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let accepting = tokio::spawn(async move {
+            let hello = Hello::new(PROTOCOL_VERSIONS, Capabilities::ALL);
+            handshake::accept(&mut server, hello, identity.as_ref()).await
+        });
+
+        let hello = Hello::new(PROTOCOL_VERSIONS, Capabilities::ALL);
+        let connected = handshake::connect(&mut client, hello, server_key.as_ref()).await;
+        // The server may still wait on the client, which gives up here
+        drop(client);
+
+        (connected, accepting.await.unwrap())
+    }
+
+    async fn sessions() -> (SessionKeys, SessionKeys) {
+        let identity = StaticSecret::random();
+        let server_key = PublicKey::from(&identity);
+
+        match handshake(Some(identity), Some(server_key)).await {
+            (Ok(client), Ok(server)) => (client.session.unwrap(), server.session.unwrap()),
+            outcome => panic!("handshake failed: {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn seals_and_opens_in_both_directions() {
+        let (mut client, mut server) = sessions().await;
+
+        for message in [&b"hwid"[..], b"", &[7; 4096]] {
+            let sealed = client.seal(message).unwrap();
+            assert_ne!(&sealed[size_of::<u64>()..], message);
+            assert_eq!(server.open(&sealed).unwrap(), message);
+
+            assert_eq!(client.open(&server.seal(message).unwrap()).unwrap(), message);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_frames() {
+        let (mut client, mut server) = sessions().await;
+
+        let mut sealed = client.seal(b"hwid").unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(matches!(server.open(&sealed), Err(DecodeError::FrameTampered)));
+        assert!(matches!(server.open(&[]), Err(DecodeError::FrameTampered)));
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_frames() {
+        let (mut client, mut server) = sessions().await;
+
+        let sealed = client.seal(b"hwid").unwrap();
+        server.open(&sealed).unwrap();
+        assert!(matches!(
+            server.open(&sealed),
+            Err(DecodeError::FrameReplayed { counter: 1, expected: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_frames_out_of_order() {
+        let (mut client, mut server) = sessions().await;
+
+        let first = client.seal(b"first").unwrap();
+        let second = client.seal(b"second").unwrap();
+        assert!(matches!(
+            server.open(&second),
+            Err(DecodeError::FrameOutOfOrder { counter: 2, expected: 1 })
+        ));
+        assert_eq!(server.open(&first).unwrap(), b"first");
+    }
+
+    #[tokio::test]
+    async fn fails_the_handshake_with_the_wrong_pinned_key() {
+        let wrong_key = PublicKey::from(&StaticSecret::random());
+
+        let (client, server) = handshake(Some(StaticSecret::random()), Some(wrong_key)).await;
+        assert!(matches!(client, Err(HandshakeError::KeyConfirmationFailed)));
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_plaintext() {
+        // A server with an identity turns away clients that don't encrypt
+        let (client, server) = handshake(Some(StaticSecret::random()), None).await;
+        assert!(matches!(client, Err(HandshakeError::Rejected(RejectReason::EncryptionRequired))));
+        assert!(matches!(server, Err(HandshakeError::EncryptionRefused)));
+
+        // A client with a pinned key doesn't follow a server that won't encrypt
+        let server_key = PublicKey::from(&StaticSecret::random());
+        let (client, _) = handshake(None, Some(server_key)).await;
+        assert!(matches!(client, Err(HandshakeError::EncryptionRefused)));
+    }
+}
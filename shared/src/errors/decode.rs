@@ -1,4 +1,4 @@
-use std::{io::Error, string::FromUtf8Error};
+use std::{io::Error, num::TryFromIntError, string::FromUtf8Error};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,6 +7,56 @@ pub enum DecodeError {
     IO(#[from] Error),
     #[error("Found a non-boolean value")]
     NonBoolValue,
+    #[error("Found an invalid Option tag ({0})")]
+    InvalidOptionTag(u8),
+    #[error("Found an invalid Result tag ({0})")]
+    InvalidResultTag(u8),
+    #[error("Found an unknown tag {tag} for enum {name}")]
+    UnknownVariant { name: &'static str, tag: i128 },
+    #[error("Received unknown packet id {0}")]
+    UnknownPacket(u16),
+    #[error("Found a duplicate key in a map or set")]
+    DuplicateKey,
+    #[error("Found an invalid char value ({0:#x})")]
+    InvalidChar(u32),
+    #[error("Found a zero value for a non-zero type")]
+    ZeroValue,
+    #[error("Found an out of range nanosecond value ({0})")]
+    InvalidNanos(u32),
+    #[error("Found a time before the Unix epoch")]
+    BeforeUnixEpoch,
+    #[error("Found a time that can't be represented on this platform")]
+    TimeOutOfRange,
+    #[error("Found an invalid IP version tag ({0})")]
+    InvalidIpVersion(u8),
+    #[error("Found a varint that overflows its target type")]
+    VarIntOverflow,
+    #[error("Found a varint with redundant trailing bytes")]
+    VarIntOverlong,
+    #[error("String of {len} bytes exceeds the limit of {max}")]
+    StringTooLong { len: usize, max: usize },
+    #[error("Collection of {len} elements exceeds the limit of {max}")]
+    TooManyElements { len: usize, max: usize },
+    #[error("Nesting exceeds the limit of {max} levels")]
+    TooDeep { max: usize },
+    #[error("Frame of {len} bytes exceeds the limit of {max}")]
+    FrameTooLong { len: usize, max: usize },
+    #[error("Frame is compressed with unknown algorithm {0}")]
+    UnknownCompression(u8),
+    #[error("Decompressed frame exceeds the limit of {max} bytes")]
+    DecompressedTooLong { max: usize },
+    #[error("Frame failed authentication, it was tampered with or sealed with another key")]
+    FrameTampered,
+    #[error("Frame {counter} was replayed, expected frame {expected}")]
+    FrameReplayed { counter: u64, expected: u64 },
+    #[error("Frame {counter} arrived out of order, expected frame {expected}")]
+    FrameOutOfOrder { counter: u64, expected: u64 },
+    #[error("Packet exceeds the decode budget of {max} bytes")]
+    BudgetExceeded { max: usize },
+    #[error("Couldn't convert {from} into {to}")]
+    Conversion { from: &'static str, to: &'static str },
+    #[error("Error occurred during conversion from integer")]
+    TryFromInt(#[from] TryFromIntError),
     #[error("Failed UTF-8 conversion")]
     FromUtf8(#[from] FromUtf8Error),
 }
@@ -1,3 +1,4 @@
+use crate::{capabilities::Capabilities, compression::Compression, prefix::LengthPrefix};
 use std::{io::Error, num::TryFromIntError};
 use thiserror::Error;
 
@@ -7,4 +8,14 @@ pub enum EncodeError {
     IO(#[from] Error),
     #[error("Error occurred during conversion from integer")]
     TryFromInt(#[from] TryFromIntError),
+    #[error("Length {len} doesn't fit into a {prefix} length prefix")]
+    LengthOverflow { len: usize, prefix: LengthPrefix },
+    #[error("Couldn't convert {from} into {to}")]
+    Conversion { from: &'static str, to: &'static str },
+    #[error("Packet {packet} needs the capabilities {missing}, which the peer lacks")]
+    MissingCapabilities { packet: u16, missing: Capabilities },
+    #[error("Compression {0:?} isn't enabled in this build")]
+    UnsupportedCompression(Compression),
+    #[error("Sent every frame the session keys allow, the connection has to be re-established")]
+    NonceExhausted,
 }
@@ -0,0 +1,30 @@
+use crate::{
+    compression::Compression,
+    errors::{decode::DecodeError, encode::EncodeError},
+    handshake::{RejectReason, VersionRange},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HandshakeError {
+    #[error("Failed to send the handshake")]
+    Encode(#[from] EncodeError),
+    #[error("Failed to read the handshake")]
+    Decode(#[from] DecodeError),
+    #[error("Peer opened with {0:?} instead of the protocol magic")]
+    InvalidMagic([u8; 4]),
+    #[error("Server rejected the connection: {0}")]
+    Rejected(RejectReason),
+    #[error("Peer speaks protocol versions {theirs}, we speak {ours}")]
+    Incompatible { ours: VersionRange, theirs: VersionRange },
+    #[error("Server picked protocol version {0}, which we never offered")]
+    UnexpectedVersion(u16),
+    #[error("Server picked compression {0:?}, which we never offered")]
+    UnexpectedCompression(Compression),
+    #[error("Peer refused to encrypt the session")]
+    EncryptionRefused,
+    #[error("Peer couldn't confirm the session keys, the pinned server key is wrong or someone is in the middle")]
+    KeyConfirmationFailed,
+    #[error("Peer sent a key share that doesn't contribute to the shared secret")]
+    WeakKeyShare,
+}
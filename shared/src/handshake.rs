@@ -0,0 +1,393 @@
+use crate::{
+    capabilities::Capabilities,
+    compression::{Compression, CompressionOffer, CompressionSettings, DEFAULT_THRESHOLD},
+    decoder::Decoder,
+    encoder::Encoder,
+    encryption::{KeyShare, PublicKey, SessionKeys, StaticSecret},
+    errors::{encode::EncodeError, handshake::HandshakeError},
+};
+use macros::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Opens every connection, so a peer speaking another protocol is turned away before anything else is read
+pub const MAGIC: [u8; 4] = *b"NPRT";
+
+/// The protocol versions this build speaks, 2 added the flags byte to the frame header
+pub const PROTOCOL_VERSIONS: VersionRange = VersionRange::new(2, 2);
+
+/// # Information
+/// The inclusive range of protocol versions one side of a connection speaks
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl VersionRange {
+    pub const fn new(min: u16, max: u16) -> Self {
+        Self { min, max }
+    }
+
+    /// The highest version both ranges contain, if they overlap at all
+    pub fn negotiate(self, other: Self) -> Option<u16> {
+        let version = self.max.min(other.max);
+        (version >= self.min.max(other.min)).then_some(version)
+    }
+}
+
+impl Display for VersionRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..={}", self.min, self.max)
+    }
+}
+
+/// # Information
+/// Why the server turned a connection away, sent back so the client can tell its user
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The connection didn't open with `MAGIC`
+    InvalidMagic,
+    /// None of the client's versions are in the `supported` range of the server
+    UnsupportedVersion { supported: VersionRange },
+    /// The server has an identity, so it only accepts clients that encrypt the session
+    EncryptionRequired,
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "the connection didn't open with the protocol magic"),
+            Self::UnsupportedVersion { supported } => write!(f, "the server only speaks protocol versions {supported}"),
+            Self::EncryptionRequired => write!(f, "the server only accepts encrypted sessions"),
+        }
+    }
+}
+
+/// # Information
+/// What one side of a connection offers during the handshake
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub versions: VersionRange,
+    pub capabilities: Capabilities,
+    /// Only used when both sides have `Capabilities::COMPRESSION`
+    pub compression: CompressionOffer,
+    /// The ephemeral public key of the sender, filled in by `connect` and `accept`
+    key_share: [u8; 32],
+}
+
+impl Hello {
+    /// Offers every compression algorithm of this build with the default threshold
+    pub fn new(versions: VersionRange, capabilities: Capabilities) -> Self {
+        Self {
+            versions,
+            capabilities,
+            compression: CompressionOffer::supported(DEFAULT_THRESHOLD),
+            key_share: [0; 32],
+        }
+    }
+
+    pub fn with_compression_threshold(mut self, threshold: u32) -> Self {
+        self.compression.threshold = threshold;
+        self
+    }
+}
+
+/// The answer of the server to the `Hello` of the client
+#[derive(Serialize, Deserialize)]
+enum HandshakeReply {
+    Accepted {
+        version: u16,
+        capabilities: Capabilities,
+        compression: CompressionSettings,
+        key_share: [u8; 32],
+    },
+    Rejected(RejectReason),
+}
+
+/// # Information
+/// What both sides agreed on during the handshake, handed to the packet handlers of the connection
+#[derive(Debug)]
+pub struct Negotiated {
+    pub version: u16,
+    /// The capabilities both sides support
+    pub capabilities: Capabilities,
+    /// How the frames are compressed, uncompressed unless both sides have `Capabilities::COMPRESSION`
+    pub compression: CompressionSettings,
+    /// The keys every frame is sealed with, `None` unless both sides have `Capabilities::ENCRYPTION`
+    pub session: Option<SessionKeys>,
+}
+
+/// # Information
+/// Opens the connection from the client side before any packet is exchanged.
+/// Sends `MAGIC` and the `Hello` of the client, then waits for the server to pick the highest common version
+/// and the capabilities and compression both support.
+/// With a pinned `server_key` the session has to be encrypted, and the server has to prove it derived the same keys
+/// before the client sends anything sealed itself. Without one the session stays in plaintext.
+pub async fn connect<S: AsyncRead + AsyncWrite + Unpin + Send>(
+    stream: &mut S,
+    mut hello: Hello,
+    server_key: Option<&PublicKey>,
+) -> Result<Negotiated, HandshakeError> {
+    let key_share = KeyShare::random();
+    hello.key_share = key_share.public.to_bytes();
+
+    // Without a pinned key there is nothing to tell the server from someone in the middle
+    if server_key.is_none() {
+        hello.capabilities = hello.capabilities.difference(Capabilities::ENCRYPTION);
+    }
+
+    MAGIC.encode(stream).await?;
+    hello.encode(stream).await?;
+    let mut transcript = encoded(&hello).await?;
+
+    read_magic(stream).await?;
+    let reply = HandshakeReply::decode(stream).await?;
+    transcript.extend(encoded(&reply).await?);
+
+    match reply {
+        HandshakeReply::Accepted { version, .. } if !(hello.versions.min..=hello.versions.max).contains(&version) => {
+            Err(HandshakeError::UnexpectedVersion(version))
+        }
+        HandshakeReply::Accepted { compression, .. }
+            if compression.algorithm != Compression::None && !hello.compression.contains(compression.algorithm) =>
+        {
+            Err(HandshakeError::UnexpectedCompression(compression.algorithm))
+        }
+        HandshakeReply::Accepted {
+            version,
+            capabilities,
+            compression,
+            key_share: server_share,
+        } => {
+            // The server already sends the intersection, this only guards against one that doesn't
+            let capabilities = capabilities.intersection(hello.capabilities);
+            let session = match (capabilities.contains(Capabilities::ENCRYPTION), server_key) {
+                (true, Some(server_key)) => {
+                    let mut session = key_share.client_session(&PublicKey::from(server_share), server_key, &transcript)?;
+                    session.confirm(&Vec::<u8>::decode(stream).await?, &transcript)?;
+                    session.confirmation(&transcript)?.encode(stream).await?;
+                    Some(session)
+                }
+                // A client that pinned a key doesn't talk to the server in plaintext
+                (false, Some(_)) => return Err(HandshakeError::EncryptionRefused),
+                (_, None) => None,
+            };
+
+            Ok(Negotiated {
+                version,
+                capabilities,
+                compression,
+                session,
+            })
+        }
+        HandshakeReply::Rejected(reason) => Err(HandshakeError::Rejected(reason)),
+    }
+}
+
+/// # Information
+/// Accepts the connection from the server side before any packet is exchanged.
+/// A client that doesn't open with `MAGIC` or shares no version with the server is told why before the error is returned.
+/// The session is only encrypted with an `identity`, the static key pair whose public key the clients pinned.
+/// With one, clients that don't offer `Capabilities::ENCRYPTION` are rejected and both sides confirm the session keys.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin + Send>(
+    stream: &mut S,
+    mut hello: Hello,
+    identity: Option<&StaticSecret>,
+) -> Result<Negotiated, HandshakeError> {
+    let key_share = KeyShare::random();
+    if identity.is_none() {
+        hello.capabilities = hello.capabilities.difference(Capabilities::ENCRYPTION);
+    }
+
+    if let Err(error) = read_magic(stream).await {
+        // Only a courtesy, the peer may already be gone and the error it caused matters more
+        let _ = reply(stream, &HandshakeReply::Rejected(RejectReason::InvalidMagic)).await;
+        return Err(error);
+    }
+
+    let offered = Hello::decode(stream).await?;
+    let mut transcript = encoded(&offered).await?;
+
+    match hello.versions.negotiate(offered.versions) {
+        Some(version) => {
+            let mut capabilities = hello.capabilities.intersection(offered.capabilities);
+            let compression = match capabilities.contains(Capabilities::COMPRESSION) {
+                true => hello.compression.negotiate(offered.compression),
+                false => CompressionSettings::default(),
+            };
+
+            // Without a common algorithm neither side can compress after all
+            if compression.algorithm == Compression::None {
+                capabilities = capabilities.difference(Capabilities::COMPRESSION);
+            }
+
+            // A server with an identity never falls back to plaintext
+            if identity.is_some() && !capabilities.contains(Capabilities::ENCRYPTION) {
+                reply(stream, &HandshakeReply::Rejected(RejectReason::EncryptionRequired)).await?;
+                return Err(HandshakeError::EncryptionRefused);
+            }
+
+            let accepted = HandshakeReply::Accepted {
+                version,
+                capabilities,
+                compression,
+                key_share: key_share.public.to_bytes(),
+            };
+            reply(stream, &accepted).await?;
+            transcript.extend(encoded(&accepted).await?);
+
+            let session = match identity {
+                Some(identity) => {
+                    let mut session = key_share.server_session(&PublicKey::from(offered.key_share), identity, &transcript)?;
+                    session.confirmation(&transcript)?.encode(stream).await?;
+                    session.confirm(&Vec::<u8>::decode(stream).await?, &transcript)?;
+                    Some(session)
+                }
+                None => None,
+            };
+
+            Ok(Negotiated {
+                version,
+                capabilities,
+                compression,
+                session,
+            })
+        }
+        None => {
+            let reason = RejectReason::UnsupportedVersion { supported: hello.versions };
+            let _ = reply(stream, &HandshakeReply::Rejected(reason)).await;
+            Err(HandshakeError::Incompatible {
+                ours: hello.versions,
+                theirs: offered.versions,
+            })
+        }
+    }
+}
+
+async fn read_magic<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(), HandshakeError> {
+    match <[u8; 4]>::decode(reader).await? {
+        MAGIC => Ok(()),
+        found => Err(HandshakeError::InvalidMagic(found)),
+    }
+}
+
+async fn reply<W: AsyncWrite + Unpin + Send>(writer: &mut W, reply: &HandshakeReply) -> Result<(), HandshakeError> {
+    MAGIC.encode(writer).await?;
+    reply.encode(writer).await?;
+    Ok(())
+}
+
+/// The bytes `value` is sent as, both sides hash them into the transcript the session keys are bound to
+async fn encoded<T: Encoder + Sync>(value: &T) -> Result<Vec<u8>, EncodeError> {
+    let mut bytes = vec![];
+    value.encode(&mut bytes).await?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    type Outcome = Result<Negotiated, HandshakeError>;
+
+    /// Runs both sides of an unencrypted handshake over a loopback stream
+    async fn handshake(client: Hello, server: Hello) -> (Outcome, Outcome) {
+        let (mut client_stream, mut server_stream) = tokio::io::duplex(4096);
+        let accepting = tokio::spawn(async move { accept(&mut server_stream, server, None).await });
+
+        let connected = connect(&mut client_stream, client, None).await;
+        drop(client_stream);
+
+        (connected, accepting.await.unwrap())
+    }
+
+    #[test]
+    fn negotiates_the_highest_common_version() {
+        assert_eq!(VersionRange::new(1, 5).negotiate(VersionRange::new(2, 3)), Some(3));
+        assert_eq!(VersionRange::new(2, 3).negotiate(VersionRange::new(3, 9)), Some(3));
+        assert_eq!(VersionRange::new(1, 2).negotiate(VersionRange::new(3, 4)), None);
+    }
+
+    #[tokio::test]
+    async fn agrees_on_the_highest_common_version() {
+        let client = Hello::new(VersionRange::new(2, 3), Capabilities::NONE);
+        let server = Hello::new(VersionRange::new(1, 5), Capabilities::NONE);
+
+        match handshake(client, server).await {
+            (Ok(client), Ok(server)) => {
+                assert_eq!(client.version, 3);
+                assert_eq!(server.version, 3);
+            }
+            outcome => panic!("handshake failed: {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_capabilities_both_sides_support() {
+        let cases = [
+            (
+                Capabilities::CHAT | Capabilities::COMPRESSION,
+                Capabilities::CHAT | Capabilities::ENCRYPTION,
+            ),
+            (Capabilities::NONE, Capabilities::ALL),
+            (Capabilities::CHAT, Capabilities::CHAT),
+        ];
+
+        for (ours, theirs) in cases {
+            let client = Hello::new(PROTOCOL_VERSIONS, ours);
+            let server = Hello::new(PROTOCOL_VERSIONS, theirs);
+
+            match handshake(client, server).await {
+                (Ok(client), Ok(server)) => {
+                    // ENCRYPTION needs an identity and COMPRESSION is never offered by both sides here, so only CHAT can survive
+                    let expected = ours.intersection(theirs).intersection(Capabilities::CHAT);
+                    assert_eq!(client.capabilities, expected);
+                    assert_eq!(server.capabilities, expected);
+                }
+                outcome => panic!("handshake failed: {outcome:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_clients_without_a_common_version() {
+        let ours = VersionRange::new(3, 4);
+        let theirs = VersionRange::new(1, 2);
+
+        let (client, server) = handshake(Hello::new(theirs, Capabilities::NONE), Hello::new(ours, Capabilities::NONE)).await;
+        assert!(matches!(
+            client,
+            Err(HandshakeError::Rejected(RejectReason::UnsupportedVersion { supported })) if supported == ours
+        ));
+        assert!(matches!(server, Err(HandshakeError::Incompatible { ours: o, theirs: t }) if o == ours && t == theirs));
+    }
+
+    #[tokio::test]
+    async fn tells_clients_with_a_bad_magic_why() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let accepting = tokio::spawn(async move { accept(&mut server, Hello::new(PROTOCOL_VERSIONS, Capabilities::ALL), None).await });
+
+        client.write_all(b"HTTP").await.unwrap();
+        assert!(matches!(accepting.await.unwrap(), Err(HandshakeError::InvalidMagic(found)) if &found == b"HTTP"));
+
+        let mut magic = [0; 4];
+        client.read_exact(&mut magic).await.unwrap();
+        assert_eq!(magic, MAGIC);
+        assert!(matches!(
+            HandshakeReply::decode(&mut client).await,
+            Ok(HandshakeReply::Rejected(RejectReason::InvalidMagic))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_a_bad_magic_after_the_client_hung_up() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client.write_all(b"HTTP").await.unwrap();
+        drop(client);
+
+        let result = accept(&mut server, Hello::new(PROTOCOL_VERSIONS, Capabilities::ALL), None).await;
+        assert!(matches!(result, Err(HandshakeError::InvalidMagic(found)) if &found == b"HTTP"));
+    }
+}
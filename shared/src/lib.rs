@@ -1,13 +1,45 @@
+#![allow(async_fn_in_trait)]
+
+// Lets the derives refer to `::shared` from within this crate as well
+extern crate self as shared;
+
+pub mod capabilities;
+pub mod codec;
+pub mod compression;
+pub mod context;
 pub mod decoder;
 pub mod encoder;
+pub mod encryption;
 pub mod errors;
+pub mod handshake;
 pub mod messages;
+pub mod prefix;
 pub mod types;
 pub mod utils;
+pub mod varint;
+
+/// Re-exports the generated code relies on, so deriving crates don't need their own `tokio` dependency
+#[doc(hidden)]
+pub mod __private {
+    pub use tokio::io::{AsyncRead, AsyncWrite};
+}
 
 pub const ADDR: &str = "127.0.0.1";
 pub const PORT: u16 = 7776;
+/// The static secret of the server, created on its first start
+pub const SERVER_KEY_PATH: &str = "server.key";
+/// The public key of the server, clients refuse to connect without it unless they are told to skip encryption
+pub const SERVER_PUBLIC_KEY_PATH: &str = "server.pub";
 
+// Handshake:
+//
+//  Magic, Hello(VersionRange, Capabilities, CompressionOffer, KeyShare): Client
+//  Magic, Accepted(Version, Capabilities, Compression, KeyShare) | Rejected(Reason): Server
+//  Confirmation(Sealed transcript hash): Server
+//  Confirmation(Sealed transcript hash): Client
+//
+// The confirmations and every frame after them are only sent when both sides have Capabilities::ENCRYPTION
+//
 // Authentication flow:
 //
 //  AuthenticationRequest(Nonce): Server
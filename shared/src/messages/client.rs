@@ -1,23 +1,23 @@
-use crate::{decoder::Decoder, encoder::Encoder, message_type, messages::EncodeError, types::Hwid};
-use macros::Networked;
+use crate::types::Hwid;
+use macros::{Networked, PacketSet};
+use std::time::SystemTime;
 
-#[derive(Debug)]
+#[derive(Debug, PacketSet)]
+#[packet_set(message_type = ClientMessageType)]
 pub enum ClientPackets {
     AuthenticationResponse(AuthenticationResponse),
     KeepAliveResponse(KeepAliveResponse),
 }
 
-message_type!(ClientMessageType; AuthenticationResponse, KeepAliveResponse);
-
 #[derive(Networked, Clone, Debug)]
-#[packet_id(0x00)]
+#[packet_id(core = 0x00)]
 pub struct AuthenticationResponse {
     pub hwid: Hwid,
     pub nonce: String,
 }
 
 #[derive(Networked, Clone, Debug)]
-#[packet_id(0x01)]
+#[packet_id(core = 0x01)]
 pub struct KeepAliveResponse {
-    pub timestamp: i64,
+    pub timestamp: SystemTime,
 }
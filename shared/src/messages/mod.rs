@@ -1,33 +1,98 @@
-use crate::errors::encode::EncodeError;
+use crate::{
+    capabilities::Capabilities,
+    context::{DecodeContext, EncodeContext},
+    errors::{decode::DecodeError, encode::EncodeError},
+};
+use std::{io::Cursor, ops::RangeInclusive};
 
 pub mod client;
 pub mod server;
 
 pub trait SystemPacket {
-    async fn to_bytes(&self) -> Result<Vec<u8>, EncodeError>;
+    /// The capabilities the peer needs for this packet, set with `#[requires(..)]`
+    const REQUIRES: Capabilities = Capabilities::NONE;
+
+    async fn to_bytes_with(&self, ctx: &EncodeContext) -> Result<Vec<u8>, EncodeError>;
+
+    /// Serializes the packet with the default `EncodeContext`
+    async fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        self.to_bytes_with(&EncodeContext::default()).await
+    }
 }
 
-#[macro_export]
-macro_rules! message_type {
-    ($name:ident; $($variant:ident),*) => {
-        use tokio::io::AsyncReadExt;
+/// # Information
+/// The id a packet is sent with, implemented by `#[derive(Networked)]` from `#[packet_id(n)]`
+/// or by the `PacketSet` that assigns it with `#[packet_set(auto)]`.
+/// Ids are written as a varint, so the core packets below 128 still take a single byte.
+pub trait PacketId {
+    const PACKET_ID: u16;
+    const NAMESPACE: PacketNamespace = PacketNamespace::of(Self::PACKET_ID);
+}
 
-        #[derive(PartialEq, Debug)]
-        pub enum $name {
-            $($variant),*,
-            InvalidEvent,
-        }
+/// # Information
+/// The reserved ranges of packet ids.
+/// `Core` is the protocol itself, `Application` the packets of the client and server
+/// and `Extensions` the packets defined by plugins.
+/// `#[packet_id(application = n)]` gives a packet the id `n` counted from the start of its namespace.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PacketNamespace {
+    Core,
+    Application,
+    Extensions,
+}
 
-        impl $name {
-            pub async fn from(cursor: &mut std::io::Cursor<Vec<u8>>) -> Self {
-                let value = cursor.read_u8().await.unwrap();
+impl PacketNamespace {
+    /// The ids reserved for the namespace
+    pub const fn range(self) -> RangeInclusive<u16> {
+        match self {
+            Self::Core => 0x0000..=0x00FF,
+            Self::Application => 0x0100..=0x7FFF,
+            Self::Extensions => 0x8000..=0xFFFF,
+        }
+    }
 
-                match value {
-                    $(x if x == $name::$variant as u8 => $name::$variant),*,
-                    _ => $name::InvalidEvent,
-                }
-            }
+    /// The namespace `id` belongs to
+    pub const fn of(id: u16) -> Self {
+        match id {
+            0x0000..=0x00FF => Self::Core,
+            0x0100..=0x7FFF => Self::Application,
+            _ => Self::Extensions,
         }
+    }
+
+    /// The id `offset` places after the start of the namespace, fails the build when used in a constant
+    /// with an offset past the end of the namespace
+    pub const fn id(self, offset: u16) -> u16 {
+        let range = self.range();
+        assert!(
+            offset <= *range.end() - *range.start(),
+            "packet id offset is past the end of its namespace"
+        );
+        *range.start() + offset
+    }
+}
+
+/// # Information
+/// The packets sent in one direction, implemented by `#[derive(PacketSet)]`.
+/// A frame is the packet id followed by the body of the packet, as written by `to_bytes` without the frame header.
+pub trait PacketSet: Sized {
+    /// The id of the packet held by the set
+    fn packet_id(&self) -> u16;
+
+    /// The capabilities the peer needs for the packet held by the set
+    fn required_capabilities(&self) -> Capabilities;
+
+    async fn to_bytes_with(&self, ctx: &EncodeContext) -> Result<Vec<u8>, EncodeError>;
+
+    /// Serializes the held packet with the default `EncodeContext`
+    async fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        self.to_bytes_with(&EncodeContext::default()).await
+    }
+
+    async fn decode_from_frame_with(frame: &mut Cursor<Vec<u8>>, ctx: &mut DecodeContext) -> Result<Self, DecodeError>;
 
-    };
+    /// Decodes the packet of a frame with the default `DecodeContext`
+    async fn decode_from_frame(frame: &mut Cursor<Vec<u8>>) -> Result<Self, DecodeError> {
+        Self::decode_from_frame_with(frame, &mut DecodeContext::default()).await
+    }
 }
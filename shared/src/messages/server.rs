@@ -1,18 +1,16 @@
-use crate::{decoder::Decoder, encoder::Encoder, message_type, messages::EncodeError};
-use macros::Networked;
-use std::time::{SystemTime, UNIX_EPOCH};
+use macros::{Networked, PacketSet};
+use std::time::SystemTime;
 use textnonce::TextNonce;
 
-#[derive(Debug)]
+#[derive(Debug, PacketSet)]
+#[packet_set(message_type = ServerMessageType)]
 pub enum ServerPackets {
     AuthenticationRequest(AuthenticationRequest),
     KeepAliveRequest(KeepAliveRequest),
 }
 
-message_type!(ServerMessageType; AuthenticationRequest, KeepAliveRequest);
-
 #[derive(Networked, Clone, Debug)]
-#[packet_id(0x00)]
+#[packet_id(core = 0x00)]
 pub struct AuthenticationRequest {
     pub nonce: String,
 }
@@ -23,16 +21,26 @@ impl AuthenticationRequest {
         }
     }
 }
+impl Default for AuthenticationRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[derive(Networked, Clone, Debug)]
-#[packet_id(0x01)]
+#[packet_id(core = 0x01)]
 pub struct KeepAliveRequest {
-    pub timestamp: i64,
+    pub timestamp: SystemTime,
 }
 impl KeepAliveRequest {
     pub fn new() -> Self {
         Self {
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+            timestamp: SystemTime::now(),
         }
     }
 }
+impl Default for KeepAliveRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::{self, Display};
+
+/// # Information
+/// Width of the length prefix written in front of strings, byte arrays and collections.
+/// `U32` is used unless a field asks for something else with `#[len(u8)]`, `#[len(u16)]` or `#[len(varint)]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LengthPrefix {
+    U8,
+    U16,
+    #[default]
+    U32,
+    /// LEB128 variable-length integer, capped at the same maximum as `U32`
+    VarInt,
+}
+
+impl LengthPrefix {
+    /// The largest length that can be written with this prefix
+    pub const fn max(self) -> usize {
+        match self {
+            LengthPrefix::U8 => u8::MAX as usize,
+            LengthPrefix::U16 => u16::MAX as usize,
+            LengthPrefix::U32 | LengthPrefix::VarInt => u32::MAX as usize,
+        }
+    }
+}
+
+impl Display for LengthPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LengthPrefix::U8 => f.write_str("u8"),
+            LengthPrefix::U16 => f.write_str("u16"),
+            LengthPrefix::U32 => f.write_str("u32"),
+            LengthPrefix::VarInt => f.write_str("varint"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{DecodeContext, EncodeContext},
+        decoder::{DecodePrefixed, Decoder},
+        encoder::{EncodePrefixed, Encoder},
+        errors::encode::EncodeError,
+    };
+    use macros::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Prefixed {
+        #[len(u8)]
+        name: String,
+        #[len(u16)]
+        data: Vec<u8>,
+        #[len(varint)]
+        ids: Vec<u32>,
+        tags: Vec<String>,
+    }
+
+    async fn prefixed<T: EncodePrefixed + ?Sized>(value: &T, prefix: LengthPrefix) -> Result<Vec<u8>, EncodeError> {
+        let mut bytes = vec![];
+        value.encode_prefixed(&mut bytes, prefix, &EncodeContext::default()).await?;
+        Ok(bytes)
+    }
+
+    #[tokio::test]
+    async fn round_trips_every_width() {
+        for (prefix, len) in [
+            (LengthPrefix::U8, 1),
+            (LengthPrefix::U16, 2),
+            (LengthPrefix::U32, 4),
+            (LengthPrefix::VarInt, 2),
+        ] {
+            let value = "x".repeat(200);
+            let bytes = prefixed(value.as_str(), prefix).await.unwrap();
+            assert_eq!(bytes.len(), len + value.len(), "{prefix}");
+
+            let decoded = String::decode_prefixed(&mut bytes.as_slice(), prefix, &mut DecodeContext::default()).await;
+            assert_eq!(decoded.unwrap(), value);
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trips_fields_with_len() {
+        let value = Prefixed {
+            name: "name".into(),
+            data: vec![1; 300],
+            ids: vec![1, 2, 3],
+            tags: vec!["tag".into()],
+        };
+
+        let mut bytes = vec![];
+        value.encode(&mut bytes).await.unwrap();
+        // Prefixes of 1, 2, 1 and 4 bytes in front of the values
+        assert_eq!(bytes.len(), 1 + 4 + 2 + 300 + 1 + 3 * 4 + 4 + 4 + 3);
+        assert_eq!(Prefixed::decode(&mut bytes.as_slice()).await.unwrap(), value);
+    }
+
+    #[tokio::test]
+    async fn rejects_lengths_beyond_the_prefix() {
+        let result = prefixed("x".repeat(256).as_str(), LengthPrefix::U8).await;
+        assert!(matches!(
+            result,
+            Err(EncodeError::LengthOverflow {
+                len: 256,
+                prefix: LengthPrefix::U8
+            })
+        ));
+        assert!(prefixed("x".repeat(255).as_str(), LengthPrefix::U8).await.is_ok());
+
+        let result = prefixed(&vec![0u8; 65_536], LengthPrefix::U16).await;
+        assert!(matches!(
+            result,
+            Err(EncodeError::LengthOverflow {
+                len: 65_536,
+                prefix: LengthPrefix::U16
+            })
+        ));
+        assert!(prefixed(&vec![0u8; 65_535], LengthPrefix::U16).await.is_ok());
+    }
+}
@@ -1,6 +1,4 @@
-use crate::{encoder::Encoder, errors::decode::DecodeError};
 use macros::{Deserialize, Serialize};
-use tokio::io::AsyncRead;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Hwid {
@@ -1,12 +1,24 @@
-use crate::errors::encode::EncodeError;
+use crate::{compression::Compression, errors::encode::EncodeError, varint::VarIntWriteExt};
 use std::mem::size_of;
 use tokio::io::AsyncWriteExt;
 
-pub async fn prepare_response(event_id: u8, mut data: Vec<u8>) -> Result<Vec<u8>, EncodeError> {
-    let mut buffer = vec![];
-    buffer.write_u32(u32::try_from(size_of::<u8>() + data.len())?).await?;
-    buffer.write_u8(event_id).await?;
-    buffer.append(&mut data);
+/// The length of the payload followed by the flags byte, which names the `Compression` of the payload
+pub const FRAME_HEADER_LEN: usize = size_of::<u32>() + size_of::<u8>();
+
+pub async fn prepare_response(event_id: u16, data: Vec<u8>) -> Result<Vec<u8>, EncodeError> {
+    let mut payload = vec![];
+    payload.write_varint(u64::from(event_id)).await?;
+    payload.extend(data);
+
+    frame(Compression::None, payload).await
+}
+
+/// Puts the frame header in front of a payload compressed with `compression`
+pub async fn frame(compression: Compression, mut payload: Vec<u8>) -> Result<Vec<u8>, EncodeError> {
+    let mut buffer = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    buffer.write_u32(u32::try_from(size_of::<u8>() + payload.len())?).await?;
+    buffer.write_u8(compression as u8).await?;
+    buffer.append(&mut payload);
 
     Ok(buffer)
 }
@@ -0,0 +1,261 @@
+use crate::{
+    context::{DecodeContext, EncodeContext},
+    decoder::{narrow, Decoder},
+    encoder::Encoder,
+    errors::{decode::DecodeError, encode::EncodeError},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// # Information
+/// Wraps an integer so it is written as a LEB128 variable-length integer instead of its fixed width.
+/// Signed integers are zigzag encoded first, so small negative values stay short as well.
+///
+/// Fields can opt into this encoding with `#[varint]` instead of using the wrapper directly.
+/// The encoding is a byte sequence, so it is unaffected by the byte order of the context.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarInt<T>(pub T);
+
+impl<T> From<T> for VarInt<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+pub trait VarIntWriteExt {
+    async fn write_varint(&mut self, value: u64) -> Result<(), EncodeError>;
+}
+
+impl<W: AsyncWrite + Unpin> VarIntWriteExt for W {
+    async fn write_varint(&mut self, mut value: u64) -> Result<(), EncodeError> {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                return Ok(self.write_u8(byte).await?);
+            }
+
+            self.write_u8(byte | 0x80).await?;
+        }
+    }
+}
+
+pub trait VarIntReadExt {
+    /// Reads a varint that has to fit into `bits` bits
+    async fn read_varint(&mut self, bits: u32) -> Result<u64, DecodeError>;
+}
+
+impl<R: AsyncRead + Unpin> VarIntReadExt for R {
+    async fn read_varint(&mut self, bits: u32) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+
+        loop {
+            if shift >= bits {
+                return Err(DecodeError::VarIntOverflow);
+            }
+
+            let byte = self.read_u8().await?;
+            let chunk = u64::from(byte & 0x7F);
+            if bits - shift < 7 && chunk >> (bits - shift) != 0 {
+                return Err(DecodeError::VarIntOverflow);
+            }
+
+            value |= chunk << shift;
+
+            if byte & 0x80 == 0 {
+                // A trailing zero byte adds nothing, so the value could have been written shorter
+                if byte == 0 && shift > 0 {
+                    return Err(DecodeError::VarIntOverlong);
+                }
+
+                return Ok(value);
+            }
+
+            shift += 7;
+        }
+    }
+}
+
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+macro_rules! impl_unsigned_varint {
+    ($($ty:ty),+) => {
+        $(
+            impl Encoder for VarInt<$ty> {
+                async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
+                    writer.write_varint(self.0 as u64).await
+                }
+            }
+
+            impl Decoder for VarInt<$ty> {
+                type Output = Self;
+
+                async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+                    // `read_varint` already rejects values wider than the target type
+                    let value = reader.read_varint(<$ty>::BITS).await?;
+                    Ok(Self(value as $ty))
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_signed_varint {
+    ($($ty:ty),+) => {
+        $(
+            impl Encoder for VarInt<$ty> {
+                async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, _ctx: &EncodeContext) -> Result<(), EncodeError> {
+                    writer.write_varint(zigzag_encode(self.0 as i64)).await
+                }
+            }
+
+            impl Decoder for VarInt<$ty> {
+                type Output = Self;
+
+                async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, _ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+                    let value = reader.read_varint(<$ty>::BITS).await?;
+                    Ok(Self(zigzag_decode(value) as $ty))
+                }
+            }
+        )+
+    };
+}
+
+impl_unsigned_varint!(u8, u16, u32, u64);
+impl_signed_varint!(i8, i16, i32, i64);
+
+/// Sizes are sent as 64 bit varints on every platform, like their fixed width codecs
+impl Encoder for VarInt<usize> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        VarInt(self.0 as u64).encode_with(writer, ctx).await
+    }
+}
+
+impl Decoder for VarInt<usize> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(Self(narrow(VarInt::<u64>::decode_with(reader, ctx).await?.0)?))
+    }
+}
+
+impl Encoder for VarInt<isize> {
+    async fn encode_with<W: AsyncWrite + Unpin + Send>(&self, writer: &mut W, ctx: &EncodeContext) -> Result<(), EncodeError> {
+        VarInt(self.0 as i64).encode_with(writer, ctx).await
+    }
+}
+
+impl Decoder for VarInt<isize> {
+    type Output = Self;
+
+    async fn decode_with<R: AsyncRead + Unpin>(reader: &mut R, ctx: &mut DecodeContext) -> Result<Self::Output, DecodeError> {
+        Ok(Self(narrow(VarInt::<i64>::decode_with(reader, ctx).await?.0)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn encoded<T>(value: T) -> Vec<u8>
+    where
+        VarInt<T>: Encoder + Sync,
+    {
+        let mut bytes = vec![];
+        VarInt(value).encode(&mut bytes).await.unwrap();
+        bytes
+    }
+
+    async fn decoded<T>(mut bytes: &[u8]) -> Result<T, DecodeError>
+    where
+        VarInt<T>: Decoder<Output = VarInt<T>>,
+    {
+        VarInt::<T>::decode(&mut bytes).await.map(|VarInt(value)| value)
+    }
+
+    #[tokio::test]
+    async fn rejects_overlong_encodings() {
+        assert!(matches!(decoded::<u32>(&[0x80, 0x00]).await, Err(DecodeError::VarIntOverlong)));
+        assert!(matches!(
+            decoded::<u64>(&[0x81, 0x80, 0x00]).await,
+            Err(DecodeError::VarIntOverlong)
+        ));
+        assert_eq!(decoded::<u32>(&[0x00]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_overflow_at_every_width() {
+        assert_eq!(decoded::<u8>(&[0xFF, 0x01]).await.unwrap(), u8::MAX);
+        assert!(matches!(decoded::<u8>(&[0x80, 0x02]).await, Err(DecodeError::VarIntOverflow)));
+        assert!(matches!(decoded::<u8>(&[0x80, 0x80, 0x01]).await, Err(DecodeError::VarIntOverflow)));
+
+        assert_eq!(decoded::<u16>(&[0xFF, 0xFF, 0x03]).await.unwrap(), u16::MAX);
+        assert!(matches!(
+            decoded::<u16>(&[0x80, 0x80, 0x04]).await,
+            Err(DecodeError::VarIntOverflow)
+        ));
+
+        assert_eq!(decoded::<u32>(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]).await.unwrap(), u32::MAX);
+        assert!(matches!(
+            decoded::<u32>(&[0x80, 0x80, 0x80, 0x80, 0x10]).await,
+            Err(DecodeError::VarIntOverflow)
+        ));
+
+        let mut max = [0xFF; 10];
+        max[9] = 0x01;
+        assert_eq!(decoded::<u64>(&max).await.unwrap(), u64::MAX);
+        max[9] = 0x02;
+        assert!(matches!(decoded::<u64>(&max).await, Err(DecodeError::VarIntOverflow)));
+        assert!(matches!(decoded::<u64>(&[0x80; 11]).await, Err(DecodeError::VarIntOverflow)));
+    }
+
+    #[tokio::test]
+    async fn zigzag_boundaries() {
+        assert_eq!(encoded(0i8).await, [0]);
+        assert_eq!(encoded(-1i8).await, [1]);
+        assert_eq!(encoded(i8::MIN).await, [255, 1]);
+        assert_eq!(encoded(i8::MAX).await, [254, 1]);
+
+        assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+        assert_eq!(zigzag_encode(i64::MAX), u64::MAX - 1);
+
+        for value in [i8::MIN, -1, 0, 1, i8::MAX] {
+            assert_eq!(decoded::<i8>(&encoded(value).await).await.unwrap(), value);
+        }
+        for value in [i64::MIN, -1, 0, 1, i64::MAX] {
+            assert_eq!(decoded::<i64>(&encoded(value).await).await.unwrap(), value);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_sizes_as_64_bits() {
+        assert_eq!(encoded(u64::MAX as usize).await, encoded(u64::MAX).await);
+        assert_eq!(encoded(isize::MIN).await, encoded(i64::MIN).await);
+        assert_eq!(decoded::<usize>(&encoded(usize::MAX).await).await.unwrap(), usize::MAX);
+        assert_eq!(decoded::<isize>(&encoded(isize::MIN).await).await.unwrap(), isize::MIN);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[tokio::test]
+    async fn rejects_sizes_beyond_the_platform() {
+        let result = decoded::<usize>(&encoded(u64::MAX).await).await;
+        assert!(matches!(result, Err(DecodeError::Conversion { from: "u64", to: "usize" })));
+
+        let result = decoded::<isize>(&encoded(i64::MIN).await).await;
+        assert!(matches!(result, Err(DecodeError::Conversion { from: "i64", to: "isize" })));
+    }
+
+    #[tokio::test]
+    async fn round_trips_u64_max() {
+        let bytes = encoded(u64::MAX).await;
+        assert_eq!(bytes.len(), 10);
+        assert_eq!(decoded::<u64>(&bytes).await.unwrap(), u64::MAX);
+    }
+}